@@ -0,0 +1,79 @@
+@group(0) @binding(0)
+var tex: texture_2d<f32>;
+@group(0) @binding(1)
+var tex_sampler: sampler;
+
+const BORDER_COLOR: vec4<f32> = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+const LABEL_COLOR: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+const GRID_COLOR: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+const GRID_STRENGTH: f32 = 0.3;
+// In screen pixels
+const BORDER_WIDTH: f32 = 2.0;
+
+struct VertexInput {
+    @location(0) pos: vec2<f32>,
+    @location(1) texel: vec2<f32>,
+    @location(2) local: vec2<f32>,
+    @location(3) center: vec2<f32>,
+    @location(4) label: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Position in the background texture, in texels
+    @location(0) texel: vec2<f32>,
+    // Position inside of the loupe, from 0 to 1
+    @location(1) local: vec2<f32>,
+    // The texel under the cursor
+    @location(2) @interpolate(flat) center: vec2<f32>,
+    // Part of the loupe height taken by the label at the bottom
+    @location(3) @interpolate(flat) label: f32,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.texel = in.texel;
+    out.local = in.local;
+    out.center = in.center;
+    out.label = in.label;
+    out.clip_position = vec4<f32>(in.pos, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Size of a screen pixel, derivatives have to be taken before branching
+    let local_width = fwidth(in.local);
+    let texel_width = fwidth(in.texel);
+
+    if any(in.local < local_width * BORDER_WIDTH) || any(in.local > 1.0 - local_width * BORDER_WIDTH) {
+        return BORDER_COLOR;
+    }
+
+    if in.local.y > 1.0 - in.label {
+        return LABEL_COLOR;
+    }
+
+    let pixel = floor(in.texel);
+    let edge = fract(in.texel);
+    let size = vec2<f32>(textureDimensions(tex));
+
+    // Outline the texel under the cursor
+    if all(pixel == in.center) && (any(edge < texel_width * BORDER_WIDTH) || any(edge > 1.0 - texel_width * BORDER_WIDTH)) {
+        return BORDER_COLOR;
+    }
+
+    var color = LABEL_COLOR;
+
+    if all(pixel >= vec2<f32>(0.0)) && all(pixel < size) {
+        color = textureLoad(tex, vec2<i32>(pixel), 0);
+    }
+
+    // Grid lines between the texels
+    if any(edge < texel_width) {
+        color = mix(color, GRID_COLOR, GRID_STRENGTH);
+    }
+
+    return color;
+}
@@ -0,0 +1,81 @@
+@group(0) @binding(0)
+var tex: texture_2d<f32>;
+@group(0) @binding(1)
+var tex_sampler: sampler;
+
+const PIXELATE: u32 = 0u;
+// Samples along each axis used to average a pixelated block
+const BLOCK_SAMPLES: i32 = 4;
+// Taps on each side of the center of the blur kernel
+const BLUR_TAPS: i32 = 6;
+
+struct VertexInput {
+    @location(0) pos: vec2<f32>,
+    @location(1) tex_pos: vec2<f32>,
+    @location(2) bounds: vec4<f32>,
+    @location(3) mode: u32,
+    @location(4) amount: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_pos: vec2<f32>,
+    // Left, top, right and bottom edge of the region in texels
+    @location(1) @interpolate(flat) bounds: vec4<f32>,
+    @location(2) @interpolate(flat) mode: u32,
+    // Block size or standard deviation of the blur in texels
+    @location(3) @interpolate(flat) amount: f32,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_pos = in.tex_pos;
+    out.bounds = in.bounds;
+    out.mode = in.mode;
+    out.amount = in.amount;
+    out.clip_position = vec4<f32>(in.pos, 0.0, 1.0);
+    return out;
+}
+
+// Sample the texture without reaching outside of the region, like the export
+fn sample_region(texel: vec2<f32>, bounds: vec4<f32>, size: vec2<f32>) -> vec4<f32> {
+    let clamped = clamp(texel, bounds.xy + 0.5, bounds.zw - 0.5);
+    return textureSampleLevel(tex, tex_sampler, clamped / size, 0.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(tex));
+    let texel = in.tex_pos * size;
+    var sum = vec4<f32>(0.0);
+
+    if in.mode == PIXELATE {
+        // The blocks start at the corner of the region
+        let block = floor((texel - in.bounds.xy) / in.amount) * in.amount + in.bounds.xy;
+
+        for (var i = 0; i < BLOCK_SAMPLES; i++) {
+            for (var j = 0; j < BLOCK_SAMPLES; j++) {
+                let offset = (vec2<f32>(f32(i), f32(j)) + 0.5) / f32(BLOCK_SAMPLES) * in.amount;
+                sum += sample_region(block + offset, in.bounds, size);
+            }
+        }
+
+        return sum / f32(BLOCK_SAMPLES * BLOCK_SAMPLES);
+    }
+
+    var weights = 0.0;
+
+    for (var i = -BLUR_TAPS; i <= BLUR_TAPS; i++) {
+        for (var j = -BLUR_TAPS; j <= BLUR_TAPS; j++) {
+            // The taps reach three standard deviations in each direction
+            let offset = vec2<f32>(f32(i), f32(j)) * in.amount * 3.0 / f32(BLUR_TAPS);
+            let weight = exp(-dot(offset, offset) / (2.0 * in.amount * in.amount));
+
+            sum += sample_region(texel + offset, in.bounds, size) * weight;
+            weights += weight;
+        }
+    }
+
+    return sum / weights;
+}
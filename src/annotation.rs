@@ -0,0 +1,1185 @@
+use std::{f32::consts::TAU, ops::Range};
+
+use image::{GenericImage, Pixel, Rgba, RgbaImage, imageops};
+use wgpu_text::glyph_brush::ab_glyph::{Font, FontArc, GlyphId, OutlinedGlyph, ScaleFont, point};
+
+use crate::{
+    traits::Contains,
+    types::{Color, Config, Extents, Rect},
+};
+
+/// Amount of segments used to approximate an ellipse
+const ELLIPSE_EDGES: u32 = 64;
+/// Samples per pixel along each axis when rasterizing onto the final image
+const SUBSAMPLES: u32 = 4;
+/// Rounds of corner cutting applied to freehand paths
+const SMOOTHING_ITERATIONS: u32 = 2;
+/// How far a miter join may extend, relative to the line width
+const MITER_LIMIT: f32 = 2.0;
+/// Bounds for resizing text annotations, in logical pixels
+const MIN_TEXT_SIZE: f32 = 8.0;
+const MAX_TEXT_SIZE: f32 = 512.0;
+/// Factor the text size changes by per scroll step
+const TEXT_RESIZE_STEP: f32 = 1.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationTool {
+    Rectangle,
+    Ellipse,
+    Line,
+    Arrow,
+    Pen,
+    Highlighter,
+    Text,
+    Pixelate,
+    Blur,
+}
+
+/// How an annotation is combined with the image below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    /// Regular alpha blending
+    Over,
+    /// Darken the image with the color, so text below stays readable
+    Multiply,
+}
+
+/// How the content below a redacted region is destroyed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+    Pixelate,
+    Blur,
+}
+
+impl Redaction {
+    /// The block size or the standard deviation of the blur, in logical pixels
+    pub fn amount(self, config: &Config) -> f32 {
+        match self {
+            Self::Pixelate => config.pixelate_size as f32,
+            Self::Blur => config.blur_sigma as f32,
+        }
+    }
+}
+
+impl AnnotationTool {
+    pub fn start(self, x: i32, y: i32, config: &Config) -> Annotation {
+        let extents = Extents {
+            start_x: x,
+            start_y: y,
+            end_x: x,
+            end_y: y,
+        };
+
+        match self {
+            Self::Rectangle => Annotation::Rectangle(extents),
+            Self::Ellipse => Annotation::Ellipse(extents),
+            Self::Line => Annotation::Line(extents),
+            Self::Arrow => Annotation::Arrow(extents),
+            Self::Pen => Annotation::Pen(vec![(x, y)]),
+            Self::Highlighter => Annotation::Highlighter(vec![(x, y)]),
+            Self::Text => Annotation::Text(Text {
+                x,
+                y,
+                size: config.annotation_text_size as f32,
+                content: String::new(),
+            }),
+            Self::Pixelate => Annotation::Redaction(Redaction::Pixelate, extents),
+            Self::Blur => Annotation::Redaction(Redaction::Blur, extents),
+        }
+    }
+}
+
+/// A shape drawn over the selection, in global coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub enum Annotation {
+    Rectangle(Extents),
+    Ellipse(Extents),
+    Line(Extents),
+    Arrow(Extents),
+    /// Freehand path
+    Pen(Vec<(i32, i32)>),
+    /// Freehand path drawn with multiply blending
+    Highlighter(Vec<(i32, i32)>),
+    Text(Text),
+    /// Region whose content is made unrecognizable
+    Redaction(Redaction, Extents),
+}
+
+/// A single line of text, drawn with the font from the config
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    /// Top left corner
+    pub x: i32,
+    pub y: i32,
+    /// Line height in logical pixels
+    pub size: f32,
+    pub content: String,
+}
+
+impl Text {
+    /// Lay out the glyphs at the given line height, with the top left corner at the origin.
+    /// Returns the outlines of the visible glyphs and the width of the line.
+    fn layout(&self, font: &FontArc, size: f32) -> (Vec<OutlinedGlyph>, f32) {
+        let scaled = font.as_scaled(size);
+        let mut glyphs = Vec::new();
+        let mut caret = 0.0;
+        let mut previous: Option<GlyphId> = None;
+
+        for c in self.content.chars() {
+            let id = scaled.glyph_id(c);
+
+            if let Some(previous) = previous {
+                caret += scaled.kern(previous, id);
+            }
+
+            glyphs.extend(
+                font.outline_glyph(id.with_scale_and_position(size, point(caret, scaled.ascent()))),
+            );
+            caret += scaled.h_advance(id);
+            previous = Some(id);
+        }
+
+        (glyphs, caret)
+    }
+
+    /// The area covered by the text in global coordinates
+    pub fn bounds(&self, font: &FontArc) -> Rect<i32> {
+        let (_, width) = self.layout(font, self.size);
+        let height = font.as_scaled(self.size).height();
+
+        // Keep empty text grabbable while it is being typed
+        Rect::new(
+            self.x,
+            self.y,
+            (width.ceil() as i32).max(self.size as i32 / 2),
+            height.ceil() as i32,
+        )
+    }
+
+    /// Draw the text onto an image, with `x`, `y` and `scale` mapping global
+    /// coordinates to pixels
+    pub fn rasterize(
+        &self,
+        image: &mut RgbaImage,
+        font: &FontArc,
+        x: f32,
+        y: f32,
+        scale: f32,
+        color: Color,
+    ) {
+        let (glyphs, _) = self.layout(font, self.size * scale);
+
+        for glyph in glyphs {
+            let bounds = glyph.px_bounds();
+
+            glyph.draw(|glyph_x, glyph_y, coverage| {
+                let pixel_x = x + bounds.min.x + glyph_x as f32;
+                let pixel_y = y + bounds.min.y + glyph_y as f32;
+
+                if pixel_x < 0.0
+                    || pixel_y < 0.0
+                    || pixel_x >= image.width() as f32
+                    || pixel_y >= image.height() as f32
+                {
+                    return;
+                }
+
+                image
+                    .get_pixel_mut(pixel_x as u32, pixel_y as u32)
+                    .blend(&Rgba([
+                        (color.r * 255.0).round() as u8,
+                        (color.g * 255.0).round() as u8,
+                        (color.b * 255.0).round() as u8,
+                        (color.a * coverage.min(1.0) * 255.0).round() as u8,
+                    ]));
+            });
+        }
+    }
+}
+
+impl Annotation {
+    /// Move the point being dragged while drawing the annotation
+    pub fn update(&mut self, x: i32, y: i32) {
+        match self {
+            Self::Rectangle(extents)
+            | Self::Ellipse(extents)
+            | Self::Line(extents)
+            | Self::Arrow(extents)
+            | Self::Redaction(_, extents) => {
+                extents.end_x = x;
+                extents.end_y = y;
+            }
+            Self::Pen(points) | Self::Highlighter(points) => {
+                if points.last() != Some(&(x, y)) {
+                    points.push((x, y));
+                }
+            }
+            Self::Text(text) => {
+                text.x = x;
+                text.y = y;
+            }
+        }
+    }
+
+    pub fn blend(&self) -> Blend {
+        match self {
+            Self::Highlighter(_) => Blend::Multiply,
+            _ => Blend::Over,
+        }
+    }
+
+    pub fn color(&self, config: &Config) -> Color {
+        match self {
+            Self::Highlighter(_) => config.highlighter_color,
+            _ => config.annotation_color,
+        }
+    }
+
+    pub fn line_width(&self, config: &Config) -> f32 {
+        match self {
+            Self::Highlighter(_) => config.highlighter_width as f32,
+            _ => config.annotation_line_width as f32,
+        }
+    }
+
+    /// Tessellate the outline of the annotation into triangles. Text and
+    /// redactions are drawn separately and result in an empty mesh.
+    pub fn to_vertices(&self, config: &Config) -> (Vec<[f32; 2]>, Vec<u32>) {
+        let mut mesh = Mesh::default();
+        let line_width = self.line_width(config);
+        let half_width = line_width / 2.0;
+
+        match self {
+            Self::Rectangle(extents) => {
+                let rect = extents.to_rect();
+                let (x, y) = (rect.x as f32, rect.y as f32);
+                let (right, bottom) = (x + rect.width as f32, y + rect.height as f32);
+
+                let (outer_x, outer_y) = (x - half_width, y - half_width);
+                let (outer_right, outer_bottom) = (right + half_width, bottom + half_width);
+                let (inner_x, inner_y) = (x + half_width, y + half_width);
+                let (inner_right, inner_bottom) = (right - half_width, bottom - half_width);
+
+                // Top and bottom bands span the whole width, the sides fill in between
+                mesh.rect(outer_x, outer_y, outer_right, inner_y);
+                mesh.rect(outer_x, inner_bottom, outer_right, outer_bottom);
+                mesh.rect(outer_x, inner_y, inner_x, inner_bottom);
+                mesh.rect(inner_right, inner_y, outer_right, inner_bottom);
+            }
+            Self::Ellipse(extents) => {
+                let rect = extents.to_rect();
+                let radius_x = rect.width as f32 / 2.0;
+                let radius_y = rect.height as f32 / 2.0;
+                let center = [rect.x as f32 + radius_x, rect.y as f32 + radius_y];
+
+                let point = |i: u32, offset: f32| {
+                    let angle = i as f32 * TAU / ELLIPSE_EDGES as f32;
+                    [
+                        center[0] + (radius_x + offset).max(0.0) * angle.cos(),
+                        center[1] + (radius_y + offset).max(0.0) * angle.sin(),
+                    ]
+                };
+
+                for i in 0..ELLIPSE_EDGES {
+                    mesh.quad([
+                        point(i, -half_width),
+                        point(i, half_width),
+                        point(i + 1, half_width),
+                        point(i + 1, -half_width),
+                    ]);
+                }
+            }
+            Self::Line(extents) => {
+                mesh.line(
+                    [extents.start_x as f32, extents.start_y as f32],
+                    [extents.end_x as f32, extents.end_y as f32],
+                    half_width,
+                );
+            }
+            Self::Arrow(extents) => {
+                let start = [extents.start_x as f32, extents.start_y as f32];
+                let end = [extents.end_x as f32, extents.end_y as f32];
+                let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+                let length = (dx * dx + dy * dy).sqrt();
+
+                if length > 0.0 {
+                    let (dir_x, dir_y) = (dx / length, dy / length);
+                    // The head is scaled with the line, but never longer than the arrow itself
+                    let head_length = (line_width * 4.0).max(10.0).min(length);
+                    let head_half_width = head_length / 2.0;
+                    let base = [end[0] - dir_x * head_length, end[1] - dir_y * head_length];
+
+                    mesh.line(start, base, half_width);
+                    mesh.triangle([
+                        end,
+                        [
+                            base[0] - dir_y * head_half_width,
+                            base[1] + dir_x * head_half_width,
+                        ],
+                        [
+                            base[0] + dir_y * head_half_width,
+                            base[1] - dir_x * head_half_width,
+                        ],
+                    ]);
+                }
+            }
+            Self::Pen(points) | Self::Highlighter(points) => {
+                let points: Vec<_> = points.iter().map(|&(x, y)| [x as f32, y as f32]).collect();
+
+                if let [point] = points[..] {
+                    mesh.disc(point, half_width);
+                } else {
+                    mesh.polyline(&smooth(points), half_width);
+                }
+            }
+            Self::Text(_) | Self::Redaction(..) => (),
+        }
+
+        (mesh.vertices, mesh.indices)
+    }
+}
+
+/// Smooth the path by repeatedly cutting its corners, keeping the end points in place
+fn smooth(mut points: Vec<[f32; 2]>) -> Vec<[f32; 2]> {
+    for _ in 0..SMOOTHING_ITERATIONS {
+        let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+            return points;
+        };
+
+        let mut smoothed = Vec::with_capacity(points.len() * 2);
+        smoothed.push(first);
+
+        for pair in points.windows(2) {
+            let [a, b] = [pair[0], pair[1]];
+            smoothed.push([0.75 * a[0] + 0.25 * b[0], 0.75 * a[1] + 0.25 * b[1]]);
+            smoothed.push([0.25 * a[0] + 0.75 * b[0], 0.25 * a[1] + 0.75 * b[1]]);
+        }
+
+        smoothed.push(last);
+        points = smoothed;
+    }
+
+    points
+}
+
+/// The annotations drawn over the selection
+#[derive(Default)]
+pub struct Annotations {
+    /// The tool used when pressing inside the selection, `None` when not annotating
+    pub tool: Option<AnnotationTool>,
+    pub finished: Vec<Annotation>,
+    /// The annotation currently being drawn
+    pub active: Option<Annotation>,
+    /// Index of the text annotation receiving the typed characters
+    pub editing: Option<usize>,
+    /// Offset from the pointer to the text being moved
+    moving: Option<(i32, i32)>,
+}
+
+impl Annotations {
+    pub fn iter(&self) -> impl Iterator<Item = &Annotation> {
+        self.finished.iter().chain(&self.active)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.finished.is_empty() && self.active.is_none()
+    }
+
+    /// The redacted regions in global coordinates
+    pub fn redactions(&self) -> impl Iterator<Item = (Redaction, Rect<i32>)> + '_ {
+        self.iter().filter_map(|annotation| match annotation {
+            Annotation::Redaction(redaction, extents) => Some((*redaction, extents.to_rect())),
+            _ => None,
+        })
+    }
+
+    /// Select the tool, or deselect it if it already was selected
+    pub fn toggle_tool(&mut self, tool: AnnotationTool) {
+        self.stop_editing();
+
+        if self.tool == Some(tool) {
+            self.tool = None;
+        } else {
+            self.tool = Some(tool);
+        }
+    }
+
+    /// Start drawing with the tool. The text tool picks up the text under the
+    /// pointer to move and edit it, or places a new one.
+    pub fn press(&mut self, tool: AnnotationTool, x: i32, y: i32, config: &Config, font: &FontArc) {
+        self.stop_editing();
+
+        if tool != AnnotationTool::Text {
+            self.active = Some(tool.start(x, y, config));
+            return;
+        }
+
+        match self.text_at(x, y, font) {
+            Some(index) => {
+                if let Annotation::Text(text) = &self.finished[index] {
+                    self.moving = Some((x - text.x, y - text.y));
+                }
+                self.editing = Some(index);
+            }
+            None => {
+                self.finished.push(tool.start(x, y, config));
+                self.editing = Some(self.finished.len() - 1);
+            }
+        }
+    }
+
+    /// Whether pointer motion is used by an annotation
+    pub fn is_dragging(&self) -> bool {
+        self.active.is_some() || self.moving.is_some()
+    }
+
+    /// Follow the pointer with the annotation being drawn or the text being moved
+    pub fn drag(&mut self, x: i32, y: i32) {
+        if let Some(annotation) = &mut self.active {
+            annotation.update(x, y);
+        } else if let (Some((offset_x, offset_y)), Some(index)) = (self.moving, self.editing) {
+            self.finished[index].update(x - offset_x, y - offset_y);
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.moving = None;
+
+        if let Some(annotation) = self.active.take() {
+            self.finished.push(annotation);
+        }
+    }
+
+    /// The topmost text annotation at the position
+    fn text_at(&self, x: i32, y: i32, font: &FontArc) -> Option<usize> {
+        self.finished.iter().rposition(|annotation| {
+            matches!(annotation, Annotation::Text(text) if text.bounds(font).contains(&(x, y)))
+        })
+    }
+
+    /// The text annotation being typed into
+    pub fn editing_text_mut(&mut self) -> Option<&mut Text> {
+        match self.finished.get_mut(self.editing?) {
+            Some(Annotation::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Replace the annotations with ones restored from the history
+    pub fn restore(&mut self, annotations: Vec<Annotation>) {
+        self.finished = annotations;
+        self.active = None;
+        self.editing = None;
+        self.moving = None;
+    }
+
+    /// Stop typing into the text annotation, discarding it if nothing was typed
+    pub fn stop_editing(&mut self) {
+        self.moving = None;
+
+        if let Some(index) = self.editing.take() {
+            if matches!(&self.finished[index], Annotation::Text(text) if text.content.is_empty()) {
+                self.finished.remove(index);
+            }
+        }
+    }
+
+    /// Grow or shrink the text being edited, or the one at the position, by
+    /// the amount of scroll steps
+    pub fn resize_text(&mut self, x: i32, y: i32, steps: f32, font: &FontArc) {
+        let Some(index) = self.editing.or_else(|| self.text_at(x, y, font)) else {
+            return;
+        };
+
+        if let Annotation::Text(text) = &mut self.finished[index] {
+            text.size =
+                (text.size * TEXT_RESIZE_STEP.powf(steps)).clamp(MIN_TEXT_SIZE, MAX_TEXT_SIZE);
+        }
+    }
+
+    /// Tessellate all annotations drawn with the given blending into a single mesh,
+    /// along with the range of indices of each annotation
+    pub fn to_vertices(
+        &self,
+        blend: Blend,
+        config: &Config,
+    ) -> (Vec<[f32; 2]>, Vec<u32>, Vec<Range<u32>>) {
+        let mut mesh = Mesh::default();
+        let mut ranges = Vec::new();
+
+        for annotation in self.iter().filter(|annotation| annotation.blend() == blend) {
+            let (vertices, indices) = annotation.to_vertices(config);
+            let start = mesh.indices.len() as u32;
+            mesh.append(vertices, indices);
+            ranges.push(start..mesh.indices.len() as u32);
+        }
+
+        (mesh.vertices, mesh.indices, ranges)
+    }
+
+    /// Draw the annotations onto an image of the area covered by `rect`. Text is
+    /// left out without a font.
+    pub fn rasterize(
+        &self,
+        image: &mut RgbaImage,
+        rect: &Rect<i32>,
+        config: &Config,
+        font: Option<&FontArc>,
+    ) {
+        let scale_x = image.width() as f32 / rect.width as f32;
+        let scale_y = image.height() as f32 / rect.height as f32;
+
+        // Redactions apply to the screenshot itself, like in the preview
+        for (redaction, region) in self.redactions() {
+            let left = ((region.x - rect.x) as f32 * scale_x).round().max(0.0) as u32;
+            let top = ((region.y - rect.y) as f32 * scale_y).round().max(0.0) as u32;
+            let right = ((region.x + region.width - rect.x) as f32 * scale_x)
+                .round()
+                .max(0.0) as u32;
+            let bottom = ((region.y + region.height - rect.y) as f32 * scale_y)
+                .round()
+                .max(0.0) as u32;
+
+            redact(
+                image,
+                Rect::new(
+                    left.min(image.width()),
+                    top.min(image.height()),
+                    right.min(image.width()).saturating_sub(left),
+                    bottom.min(image.height()).saturating_sub(top),
+                ),
+                redaction,
+                redaction.amount(config) * scale_y,
+            );
+        }
+
+        for annotation in self.iter() {
+            if let Annotation::Text(text) = annotation {
+                // Outlined at the size of the image, so the glyphs stay sharp
+                if let Some(font) = font {
+                    text.rasterize(
+                        image,
+                        font,
+                        (text.x - rect.x) as f32 * scale_x,
+                        (text.y - rect.y) as f32 * scale_y,
+                        scale_y,
+                        annotation.color(config),
+                    );
+                }
+                continue;
+            }
+
+            let (mut vertices, indices) = annotation.to_vertices(config);
+
+            for vertex in &mut vertices {
+                vertex[0] = (vertex[0] - rect.x as f32) * scale_x;
+                vertex[1] = (vertex[1] - rect.y as f32) * scale_y;
+            }
+
+            fill_triangles(
+                image,
+                &vertices,
+                &indices,
+                annotation.color(config),
+                annotation.blend(),
+            );
+        }
+    }
+}
+
+/// Overwrite the pixels in the region, so the original content can't be
+/// recovered from the image. `amount` is in pixels of the image.
+fn redact(image: &mut RgbaImage, region: Rect<u32>, redaction: Redaction, amount: f32) {
+    if region.width == 0 || region.height == 0 {
+        return;
+    }
+
+    match redaction {
+        Redaction::Pixelate => {
+            let block = (amount.round() as u32).max(1);
+
+            // The blocks start at the corner of the region and get cut off at its edges
+            for block_y in (region.y..region.y + region.height).step_by(block as usize) {
+                for block_x in (region.x..region.x + region.width).step_by(block as usize) {
+                    let width = block.min(region.x + region.width - block_x);
+                    let height = block.min(region.y + region.height - block_y);
+
+                    let mut sum = [0u32; 4];
+                    for y in block_y..block_y + height {
+                        for x in block_x..block_x + width {
+                            for (sum, channel) in sum.iter_mut().zip(image.get_pixel(x, y).0) {
+                                *sum += channel as u32;
+                            }
+                        }
+                    }
+
+                    let average = Rgba(sum.map(|sum| (sum / (width * height)) as u8));
+                    for y in block_y..block_y + height {
+                        for x in block_x..block_x + width {
+                            image.put_pixel(x, y, average);
+                        }
+                    }
+                }
+            }
+        }
+        Redaction::Blur => {
+            // Only the pixels inside the region are blurred, nothing outside of it leaks in
+            let cropped =
+                imageops::crop_imm(image, region.x, region.y, region.width, region.height)
+                    .to_image();
+            let blurred = imageops::blur(&cropped, amount.max(1.0));
+
+            image
+                .copy_from(&blurred, region.x, region.y)
+                .expect("Blurred region is inside the image");
+        }
+    }
+}
+
+/// Triangles built up while tessellating
+#[derive(Default)]
+struct Mesh {
+    vertices: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl Mesh {
+    fn append(&mut self, vertices: Vec<[f32; 2]>, indices: Vec<u32>) {
+        let offset = self.vertices.len() as u32;
+
+        self.vertices.extend(vertices);
+        self.indices
+            .extend(indices.into_iter().map(|index| index + offset));
+    }
+
+    fn triangle(&mut self, points: [[f32; 2]; 3]) {
+        self.append(points.to_vec(), vec![0, 1, 2]);
+    }
+
+    /// Points are expected to go around the quad
+    fn quad(&mut self, points: [[f32; 2]; 4]) {
+        self.append(points.to_vec(), vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    fn rect(&mut self, x: f32, y: f32, right: f32, bottom: f32) {
+        self.quad([[x, y], [right, y], [right, bottom], [x, bottom]]);
+    }
+
+    fn disc(&mut self, center: [f32; 2], radius: f32) {
+        let mut vertices = vec![center];
+        let mut indices = Vec::new();
+
+        for i in 0..ELLIPSE_EDGES {
+            let angle = i as f32 * TAU / ELLIPSE_EDGES as f32;
+            vertices.push([
+                center[0] + radius * angle.cos(),
+                center[1] + radius * angle.sin(),
+            ]);
+            indices.extend([0, i + 1, (i + 1) % ELLIPSE_EDGES + 1]);
+        }
+
+        self.append(vertices, indices);
+    }
+
+    /// A strip along the points with mitered joins, so consecutive segments don't overlap
+    fn polyline(&mut self, points: &[[f32; 2]], half_width: f32) {
+        let normal = |a: [f32; 2], b: [f32; 2]| {
+            let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+            let length = (dx * dx + dy * dy).sqrt();
+            if length == 0.0 {
+                [0.0, 0.0]
+            } else {
+                [-dy / length, dx / length]
+            }
+        };
+
+        let mut vertices = Vec::with_capacity(points.len() * 2);
+
+        for (i, point) in points.iter().enumerate() {
+            let before = normal(points[i.saturating_sub(1)], *point);
+            let after = normal(*point, points[(i + 1).min(points.len() - 1)]);
+
+            // The end points only have a single segment to follow
+            let (before, after) = match (before == [0.0, 0.0], after == [0.0, 0.0]) {
+                (true, _) => (after, after),
+                (_, true) => (before, before),
+                _ => (before, after),
+            };
+
+            let sum = [before[0] + after[0], before[1] + after[1]];
+            let length = (sum[0] * sum[0] + sum[1] * sum[1]).sqrt();
+
+            let offset = if length == 0.0 {
+                // The path folds back onto itself
+                [before[0] * half_width, before[1] * half_width]
+            } else {
+                let miter = [sum[0] / length, sum[1] / length];
+                // Lengthen the miter so the line keeps its width through the join
+                let cos = miter[0] * after[0] + miter[1] * after[1];
+                let scale = half_width / cos.max(1.0 / MITER_LIMIT);
+                [miter[0] * scale, miter[1] * scale]
+            };
+
+            vertices.push([point[0] + offset[0], point[1] + offset[1]]);
+            vertices.push([point[0] - offset[0], point[1] - offset[1]]);
+        }
+
+        let indices = (0..points.len().saturating_sub(1) as u32)
+            .flat_map(|i| {
+                let i = i * 2;
+                [i, i + 1, i + 3, i, i + 3, i + 2]
+            })
+            .collect();
+
+        self.append(vertices, indices);
+    }
+
+    fn line(&mut self, start: [f32; 2], end: [f32; 2], half_width: f32) {
+        let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+        let length = (dx * dx + dy * dy).sqrt();
+
+        if length == 0.0 {
+            return;
+        }
+
+        let normal = [-dy / length * half_width, dx / length * half_width];
+
+        self.quad([
+            [start[0] + normal[0], start[1] + normal[1]],
+            [end[0] + normal[0], end[1] + normal[1]],
+            [end[0] - normal[0], end[1] - normal[1]],
+            [start[0] - normal[0], start[1] - normal[1]],
+        ]);
+    }
+}
+
+/// Blend the triangles onto the image with anti-aliasing. Overlapping triangles
+/// are only blended once, so translucent colors stay uniform.
+fn fill_triangles(
+    image: &mut RgbaImage,
+    vertices: &[[f32; 2]],
+    indices: &[u32],
+    color: Color,
+    blend: Blend,
+) {
+    let (Some(min_x), Some(min_y), Some(max_x), Some(max_y)) = (
+        vertices.iter().map(|v| v[0]).reduce(f32::min),
+        vertices.iter().map(|v| v[1]).reduce(f32::min),
+        vertices.iter().map(|v| v[0]).reduce(f32::max),
+        vertices.iter().map(|v| v[1]).reduce(f32::max),
+    ) else {
+        return;
+    };
+
+    let min_x = (min_x.floor().max(0.0) as u32).min(image.width());
+    let min_y = (min_y.floor().max(0.0) as u32).min(image.height());
+    let max_x = (max_x.ceil().max(0.0) as u32).min(image.width());
+    let max_y = (max_y.ceil().max(0.0) as u32).min(image.height());
+
+    if min_x == max_x || min_y == max_y {
+        return;
+    }
+
+    let width = (max_x - min_x) as usize;
+    // One bit per covered sample of each pixel
+    let mut coverage = vec![0u16; width * (max_y - min_y) as usize];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+
+        let left = (a[0].min(b[0]).min(c[0]).floor().max(min_x as f32) as u32).min(max_x);
+        let top = (a[1].min(b[1]).min(c[1]).floor().max(min_y as f32) as u32).min(max_y);
+        let right = (a[0].max(b[0]).max(c[0]).ceil().max(min_x as f32) as u32).min(max_x);
+        let bottom = (a[1].max(b[1]).max(c[1]).ceil().max(min_y as f32) as u32).min(max_y);
+
+        for y in top..bottom {
+            for x in left..right {
+                let mask = &mut coverage[(y - min_y) as usize * width + (x - min_x) as usize];
+
+                for sample in 0..SUBSAMPLES * SUBSAMPLES {
+                    let point = [
+                        x as f32 + ((sample % SUBSAMPLES) as f32 + 0.5) / SUBSAMPLES as f32,
+                        y as f32 + ((sample / SUBSAMPLES) as f32 + 0.5) / SUBSAMPLES as f32,
+                    ];
+
+                    if triangle_contains(a, b, c, point) {
+                        *mask |= 1 << sample;
+                    }
+                }
+            }
+        }
+    }
+
+    for (i, mask) in coverage.into_iter().enumerate() {
+        if mask == 0 {
+            continue;
+        }
+
+        let x = min_x + (i % width) as u32;
+        let y = min_y + (i / width) as u32;
+        let alpha = color.a * mask.count_ones() as f32 / (SUBSAMPLES * SUBSAMPLES) as f32;
+        let pixel = image.get_pixel_mut(x, y);
+
+        match blend {
+            Blend::Over => pixel.blend(&Rgba([
+                (color.r * 255.0).round() as u8,
+                (color.g * 255.0).round() as u8,
+                (color.b * 255.0).round() as u8,
+                (alpha * 255.0).round() as u8,
+            ])),
+            Blend::Multiply => {
+                for (channel, factor) in pixel.0.iter_mut().zip([color.r, color.g, color.b]) {
+                    let factor = 1.0 - alpha * (1.0 - factor);
+                    *channel = (*channel as f32 * factor).round() as u8;
+                }
+            }
+        }
+    }
+}
+
+fn triangle_contains(a: [f32; 2], b: [f32; 2], c: [f32; 2], p: [f32; 2]) -> bool {
+    let edge = |from: [f32; 2], to: [f32; 2]| {
+        (to[0] - from[0]) * (p[1] - from[1]) - (to[1] - from[1]) * (p[0] - from[0])
+    };
+
+    let (ab, bc, ca) = (edge(a, b), edge(b, c), edge(c, a));
+
+    // Either winding order is accepted
+    (ab >= 0.0 && bc >= 0.0 && ca >= 0.0) || (ab <= 0.0 && bc <= 0.0 && ca <= 0.0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    /// A monospace font bundled with the tests, so they don't depend on the system fonts
+    pub(crate) fn font() -> FontArc {
+        FontArc::try_from_slice(include_bytes!("../res/fixtures/DejaVuSansMono.ttf")).unwrap()
+    }
+
+    fn extents(start_x: i32, start_y: i32, end_x: i32, end_y: i32) -> Extents {
+        Extents {
+            start_x,
+            start_y,
+            end_x,
+            end_y,
+        }
+    }
+
+    /// Rasterize the annotations onto a white image of the area
+    fn rasterize(annotations: Vec<Annotation>, rect: Rect<i32>, size: (u32, u32)) -> RgbaImage {
+        rasterize_with_font(annotations, rect, size, None)
+    }
+
+    fn rasterize_with_font(
+        annotations: Vec<Annotation>,
+        rect: Rect<i32>,
+        size: (u32, u32),
+        font: Option<&FontArc>,
+    ) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(size.0, size.1, WHITE);
+        let annotations = Annotations {
+            finished: annotations,
+            ..Default::default()
+        };
+
+        annotations.rasterize(&mut image, &rect, &Config::default(), font);
+        image
+    }
+
+    fn assert_pixels(image: &RgbaImage, pixels: &[(u32, u32)], color: Rgba<u8>) {
+        for &(x, y) in pixels {
+            assert_eq!(*image.get_pixel(x, y), color, "pixel at {},{}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_rasterize_shapes() {
+        let rect = Rect::new(0, 0, 100, 100);
+
+        // The 3 pixel wide outline is centered on the edges
+        let image = rasterize(
+            vec![Annotation::Rectangle(extents(10, 10, 50, 40))],
+            rect,
+            (100, 100),
+        );
+        assert_pixels(&image, &[(10, 25), (49, 25), (30, 10), (30, 39)], RED);
+        assert_pixels(&image, &[(30, 25), (5, 25), (55, 25), (30, 5)], WHITE);
+
+        let image = rasterize(
+            vec![Annotation::Ellipse(extents(10, 10, 50, 50))],
+            rect,
+            (100, 100),
+        );
+        assert_pixels(&image, &[(30, 10), (10, 30), (49, 30), (30, 49)], RED);
+        assert_pixels(&image, &[(30, 30), (12, 12), (48, 48)], WHITE);
+
+        let image = rasterize(
+            vec![Annotation::Line(extents(10, 10, 60, 10))],
+            rect,
+            (100, 100),
+        );
+        assert_pixels(&image, &[(10, 10), (30, 9), (30, 10), (59, 10)], RED);
+        assert_pixels(&image, &[(30, 7), (30, 12), (70, 10)], WHITE);
+
+        // The head is four times the line width long and half as wide
+        let image = rasterize(
+            vec![Annotation::Arrow(extents(10, 50, 80, 50))],
+            rect,
+            (100, 100),
+        );
+        assert_pixels(&image, &[(40, 50), (70, 46), (70, 53), (77, 50)], RED);
+        assert_pixels(&image, &[(40, 53), (65, 50 - 6), (82, 50)], WHITE);
+
+        let image = rasterize(
+            vec![Annotation::Pen(vec![(10, 10), (40, 10), (40, 40)])],
+            rect,
+            (100, 100),
+        );
+        assert_pixels(&image, &[(10, 10), (20, 10), (40, 30), (40, 39)], RED);
+        assert_pixels(&image, &[(25, 25), (20, 14), (44, 30)], WHITE);
+    }
+
+    #[test]
+    fn test_rasterize_highlighted_text() {
+        let rect = Rect::new(0, 0, 100, 50);
+        let text = Annotation::Text(Text {
+            x: 10,
+            y: 10,
+            size: 24.0,
+            content: "WWW".to_string(),
+        });
+        let highlight = Annotation::Highlighter(vec![(0, 22), (40, 22), (100, 22)]);
+
+        let font = font();
+        let text_only = rasterize_with_font(vec![text.clone()], rect, (100, 50), Some(&font));
+        let highlight_only = rasterize(vec![highlight.clone()], rect, (100, 50));
+        let highlighted = rasterize_with_font(vec![text, highlight], rect, (100, 50), Some(&font));
+
+        let highlight_color = *highlight_only.get_pixel(5, 22);
+        assert_ne!(highlight_color, WHITE);
+
+        // The glyphs inside the stroke keep their color, the gaps between them get the highlight
+        let covered: Vec<_> = (0..100)
+            .flat_map(|x| (16..28).map(move |y| (x, y)))
+            .filter(|&(x, y)| *text_only.get_pixel(x, y) == RED)
+            .collect();
+        assert!(!covered.is_empty());
+        assert_pixels(&highlighted, &covered, RED);
+        assert_pixels(&highlighted, &[(5, 22), (95, 22)], highlight_color);
+
+        // A stroke going back over itself isn't darker where it overlaps
+        let doubled = rasterize(
+            vec![Annotation::Highlighter(vec![(0, 40), (90, 40), (20, 40)])],
+            rect,
+            (100, 50),
+        );
+        assert_pixels(&doubled, &[(50, 40)], highlight_color);
+    }
+
+    fn text(content: &str) -> Text {
+        Text {
+            x: 10,
+            y: 10,
+            size: 24.0,
+            content: content.to_string(),
+        }
+    }
+
+    /// The area of the pixels that aren't white
+    fn inked(image: &RgbaImage) -> Rect<u32> {
+        let inked: Vec<_> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel != WHITE)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let left = inked.iter().map(|(x, _)| *x).min().unwrap();
+        let top = inked.iter().map(|(_, y)| *y).min().unwrap();
+        let right = inked.iter().map(|(x, _)| *x).max().unwrap();
+        let bottom = inked.iter().map(|(_, y)| *y).max().unwrap();
+
+        Rect::new(left, top, right - left + 1, bottom - top + 1)
+    }
+
+    #[test]
+    fn test_text_layout() {
+        let font = font();
+
+        let (glyphs, width) = text("Hi there").layout(&font, 24.0);
+        // The space has no outline
+        assert_eq!(glyphs.len(), 7);
+        assert!(width > 0.0);
+
+        let (_, double_width) = text("Hi there").layout(&font, 48.0);
+        assert!((double_width - 2.0 * width).abs() < 1.0);
+
+        let bounds = text("Hi there").bounds(&font);
+        assert_eq!((bounds.x, bounds.y), (10, 10));
+        assert_eq!(bounds.width, width.ceil() as i32);
+        assert!(bounds.height >= 24);
+        assert!(bounds.contains(&(12, 20)));
+
+        // Empty text still has a size, to be clicked on
+        let bounds = text("").bounds(&font);
+        assert_eq!((bounds.width, bounds.height > 0), (12, true));
+    }
+
+    #[test]
+    fn test_rasterize_text_scaled() {
+        let annotation = Annotation::Text(text("Hi"));
+        let rect = Rect::new(0, 0, 100, 50);
+
+        let font = font();
+        let image = rasterize_with_font(vec![annotation.clone()], rect, (100, 50), Some(&font));
+        let scaled = rasterize_with_font(vec![annotation], rect, (200, 100), Some(&font));
+
+        // Outlined at twice the size, not stretched from the logical size
+        let (inked, inked_scaled) = (inked(&image), inked(&scaled));
+        assert!(inked.x >= 10 && inked.y >= 10);
+        assert!(inked_scaled.x.abs_diff(2 * inked.x) <= 1);
+        assert!(inked_scaled.height.abs_diff(2 * inked.height) <= 2);
+        assert!(inked_scaled.width.abs_diff(2 * inked.width) <= 2);
+        assert!(
+            scaled.pixels().filter(|pixel| **pixel == RED).count()
+                > 3 * image.pixels().filter(|pixel| **pixel == RED).count()
+        );
+    }
+
+    #[test]
+    fn test_resize_text() {
+        let font = font();
+        let mut annotations = Annotations {
+            finished: vec![Annotation::Text(text("Hi"))],
+            ..Default::default()
+        };
+        let size = |annotations: &Annotations| match &annotations.finished[0] {
+            Annotation::Text(text) => text.size,
+            _ => unreachable!(),
+        };
+
+        // Scrolling next to the text does nothing
+        annotations.resize_text(80, 80, 1.0, &font);
+        assert_eq!(size(&annotations), 24.0);
+
+        annotations.resize_text(12, 20, 1.0, &font);
+        assert!((size(&annotations) - 24.0 * TEXT_RESIZE_STEP).abs() < 0.001);
+        annotations.resize_text(12, 20, -1.0, &font);
+        assert!((size(&annotations) - 24.0).abs() < 0.001);
+
+        annotations.resize_text(12, 20, 100.0, &font);
+        assert_eq!(size(&annotations), MAX_TEXT_SIZE);
+
+        // The text being edited is resized wherever the pointer is
+        annotations.editing = Some(0);
+        annotations.resize_text(1000, 1000, -100.0, &font);
+        assert_eq!(size(&annotations), MIN_TEXT_SIZE);
+    }
+
+    #[test]
+    fn test_rasterize_redactions() {
+        // A checkerboard, which every redaction turns gray
+        let original = RgbaImage::from_fn(100, 100, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                WHITE
+            }
+        });
+        let rect = Rect::new(100, 100, 50, 50);
+
+        for redaction in [Redaction::Pixelate, Redaction::Blur] {
+            let mut image = original.clone();
+            let annotations = Annotations {
+                finished: vec![Annotation::Redaction(
+                    redaction,
+                    extents(110, 120, 130, 160),
+                )],
+                ..Default::default()
+            };
+            annotations.rasterize(&mut image, &rect, &Config::default(), None);
+
+            // At twice the resolution the region covers 20..60 horizontally, and 40
+            // to the bottom of the image vertically
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let inside = (20..60).contains(&x) && y >= 40;
+                assert_eq!(
+                    pixel != original.get_pixel(x, y),
+                    inside,
+                    "{:?} at {},{}",
+                    redaction,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_triangles_overlap() {
+        let mut image = RgbaImage::from_pixel(4, 2, WHITE);
+        let color = Color {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            a: 0.5,
+        };
+
+        // Two squares sharing the middle column, which is only blended once
+        let vertices = [
+            [0.0, 0.0],
+            [3.0, 0.0],
+            [3.0, 2.0],
+            [0.0, 2.0],
+            [1.0, 0.0],
+            [4.0, 0.0],
+            [4.0, 2.0],
+            [1.0, 2.0],
+        ];
+        let indices = [0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7];
+        fill_triangles(&mut image, &vertices, &indices, color, Blend::Over);
+
+        let expected = *image.get_pixel(0, 0);
+        assert_ne!(expected, WHITE);
+        assert!(image.pixels().all(|pixel| *pixel == expected));
+    }
+
+    #[test]
+    fn test_smooth() {
+        let smoothed = smooth(vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]]);
+
+        assert_eq!(smoothed.first(), Some(&[0.0, 0.0]));
+        assert_eq!(smoothed.last(), Some(&[10.0, 10.0]));
+        // The corner is cut off
+        assert!(!smoothed.contains(&[10.0, 0.0]));
+    }
+
+    #[test]
+    fn test_rasterize_clipped() {
+        // The area starts at 100,100 and has twice as many pixels as logical pixels
+        let rect = Rect::new(100, 100, 50, 50);
+
+        let image = rasterize(
+            vec![
+                // Sticks out of the top left corner of the area
+                Annotation::Rectangle(extents(80, 80, 120, 120)),
+                // Entirely outside of the area
+                Annotation::Line(extents(0, 0, 90, 90)),
+                Annotation::Pen(vec![(160, 110), (200, 110)]),
+            ],
+            rect,
+            (100, 100),
+        );
+
+        assert_pixels(&image, &[(40, 0), (40, 39), (0, 40), (39, 40)], RED);
+        assert_pixels(&image, &[(20, 20), (43, 20), (20, 43), (99, 20)], WHITE);
+        assert_eq!(
+            image.pixels().filter(|pixel| **pixel != WHITE).count(),
+            // The right and bottom edges of the outline, 3 logical pixels wide
+            6 * 43 + 6 * 37,
+            "Nothing is drawn outside of the outline"
+        );
+    }
+}
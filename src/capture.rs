@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local};
+use image::{DynamicImage, GenericImage, imageops::FilterType};
+use libwayshot::WayshotConnection;
+use wayland_client::protocol::wl_output::Transform;
+
+use crate::{
+    traits::{Contains, ToLocal},
+    types::{CropScale, Rect},
+};
+
+/// The screenshot of a single output at its native resolution, oriented like
+/// the output is laid out rather than like its buffer
+pub struct OutputCapture {
+    pub name: String,
+    /// Logical position and size of the output in global coordinates
+    pub rect: Rect<i32>,
+    pub image: DynamicImage,
+}
+
+impl OutputCapture {
+    /// Pixels of the screenshot per logical pixel
+    pub fn scale(&self) -> f32 {
+        self.image.width() as f32 / self.rect.width as f32
+    }
+}
+
+/// The screenshots of all outputs, each grabbed exactly once. Everything shown
+/// and cropped is derived from these.
+#[derive(Default)]
+pub struct Capture {
+    pub outputs: Vec<OutputCapture>,
+    /// When the outputs were grabbed
+    pub time: DateTime<Local>,
+    /// Whether the cursor is included, also for outputs added later
+    cursor: bool,
+}
+
+impl Capture {
+    /// Grab every output at once, so they all show the same moment. Outputs that
+    /// fail to be captured are left out, an error means nothing can be captured.
+    pub fn take(cursor: bool) -> libwayshot::Result<Self> {
+        let wayshot_connection = WayshotConnection::new()?;
+        let time = Local::now();
+        let outputs = wayshot_connection.get_all_outputs();
+
+        // The frames of all outputs are requested before waiting for any of them
+        let mut images: HashMap<String, DynamicImage> =
+            match wayshot_connection.screenshot_outputs(outputs, cursor) {
+                Ok(images) => images
+                    .into_iter()
+                    .map(|(output, image)| (output.name, image))
+                    .collect(),
+                Err(why) => {
+                    log::warn!(
+                        "Failed to capture the outputs at once, capturing them one by one: {}",
+                        why
+                    );
+
+                    outputs
+                        .iter()
+                        .filter_map(|output| {
+                            match wayshot_connection.screenshot_single_output(output, cursor) {
+                                Ok(image) => Some((output.name.clone(), image)),
+                                Err(why) => {
+                                    log::error!(
+                                        "Failed to capture output {}, leaving it out: {}",
+                                        output.name,
+                                        why
+                                    );
+                                    None
+                                }
+                            }
+                        })
+                        .collect()
+                }
+            };
+
+        let outputs = outputs
+            .iter()
+            .filter_map(|output| {
+                let region = output.logical_region.inner;
+
+                Some(OutputCapture {
+                    name: output.name.clone(),
+                    rect: Rect::new(
+                        region.position.x,
+                        region.position.y,
+                        region.size.width as i32,
+                        region.size.height as i32,
+                    ),
+                    image: to_logical(images.remove(&output.name)?, output.transform),
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            outputs,
+            time,
+            cursor,
+        })
+    }
+
+    /// Grab an output that appeared after the others were captured, replacing
+    /// any earlier screenshot of it. Returns false if it couldn't be captured.
+    pub fn add_output(&mut self, name: Option<&str>, rect: &Rect<i32>) -> bool {
+        let Ok(wayshot_connection) = WayshotConnection::new() else {
+            return false;
+        };
+
+        let Some(output) = wayshot_connection.get_all_outputs().iter().find(|output| {
+            let region = output.logical_region.inner;
+
+            match name {
+                Some(name) => output.name == name,
+                None => region.position.x == rect.x && region.position.y == rect.y,
+            }
+        }) else {
+            return false;
+        };
+
+        let image = match wayshot_connection.screenshot_single_output(output, self.cursor) {
+            Ok(image) => to_logical(image, output.transform),
+            Err(why) => {
+                log::error!("Failed to capture output {}: {}", output.name, why);
+                return false;
+            }
+        };
+
+        self.insert_output(OutputCapture {
+            name: output.name.clone(),
+            rect: *rect,
+            image,
+        });
+
+        true
+    }
+
+    /// Add the screenshot of an output, replacing the one taken of it before
+    fn insert_output(&mut self, output: OutputCapture) {
+        self.remove_output(&output.name);
+        self.outputs.push(output);
+    }
+
+    /// Follow an output that moved. The screenshot stays the one taken before, only
+    /// the area it covers changes.
+    pub fn move_output(&mut self, name: Option<&str>, from: &Rect<i32>, to: Rect<i32>) {
+        let output = match name {
+            Some(name) => self.outputs.iter_mut().find(|output| output.name == name),
+            None => self.outputs.iter_mut().find(|output| output.rect == *from),
+        };
+
+        if let Some(output) = output {
+            output.rect = to;
+        }
+    }
+
+    pub fn remove_output(&mut self, name: &str) {
+        self.outputs.retain(|output| output.name != name);
+    }
+
+    /// The screenshot of the output with the name, or at the same place if it has none
+    pub fn output(&self, name: Option<&str>, rect: &Rect<i32>) -> Option<&OutputCapture> {
+        match name {
+            Some(name) => self.outputs.iter().find(|output| output.name == name),
+            None => self.outputs.iter().find(|output| output.rect == *rect),
+        }
+    }
+
+    /// The screenshot of the output the point in global coordinates is on
+    pub fn output_at(&self, point: (i32, i32)) -> Option<&OutputCapture> {
+        self.outputs
+            .iter()
+            .find(|output| output.rect.contains(&point))
+    }
+
+    /// Crop the rectangle in global coordinates out of the screenshots. Parts of
+    /// outputs with a different scale are resampled to the target scale, gaps
+    /// between outputs stay transparent.
+    pub fn crop(&self, rect: &Rect<i32>, crop_scale: CropScale) -> DynamicImage {
+        // Straight from the native screenshot if the rectangle is on a single output
+        if let Some(output) = self
+            .outputs
+            .iter()
+            .find(|output| output.rect.contains(rect))
+        {
+            let local = rect.to_local(&output.rect);
+            let scale = output.scale();
+
+            return output.image.crop_imm(
+                (local.x as f32 * scale).round() as u32,
+                (local.y as f32 * scale).round() as u32,
+                (local.width as f32 * scale).round() as u32,
+                (local.height as f32 * scale).round() as u32,
+            );
+        }
+
+        let parts = self
+            .outputs
+            .iter()
+            .filter_map(|output| Some((output, rect.intersection(&output.rect)?)))
+            .collect::<Vec<_>>();
+
+        let scales = parts.iter().map(|(output, _)| output.scale());
+        let scale = match crop_scale {
+            CropScale::Max => scales.fold(f32::MIN, f32::max),
+            CropScale::Min => scales.fold(f32::MAX, f32::min),
+            CropScale::Fixed(scale) => scale,
+        };
+        // Nothing of the rectangle is on any output
+        let scale = if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
+        };
+
+        // Rounding the edges instead of the sizes keeps adjacent parts from overlapping or leaving gaps
+        let to_pixels = |value: i32| (value as f32 * scale).round() as u32;
+
+        let mut image = DynamicImage::new_rgba8(to_pixels(rect.width), to_pixels(rect.height));
+
+        for (output, part) in parts {
+            let local = part.to_local(&output.rect);
+            let output_scale = output.scale();
+            let native = output.image.crop_imm(
+                (local.x as f32 * output_scale).round() as u32,
+                (local.y as f32 * output_scale).round() as u32,
+                (local.width as f32 * output_scale).round() as u32,
+                (local.height as f32 * output_scale).round() as u32,
+            );
+
+            let x = to_pixels(part.x - rect.x);
+            let y = to_pixels(part.y - rect.y);
+            let width = to_pixels(part.x + part.width - rect.x) - x;
+            let height = to_pixels(part.y + part.height - rect.y) - y;
+
+            let resampled;
+            let part_image = if native.width() == width && native.height() == height {
+                &native
+            } else {
+                resampled = native.resize_exact(width, height, FilterType::Lanczos3);
+                &resampled
+            };
+
+            if let Err(why) = image.copy_from(part_image, x, y) {
+                log::error!("Failed to add output {} to the crop: {}", output.name, why);
+            }
+        }
+
+        image
+    }
+}
+
+/// Undo the output transform the screenshot was taken with, so rotated and
+/// flipped outputs end up in the orientation of the logical layout
+fn to_logical(image: DynamicImage, transform: Transform) -> DynamicImage {
+    match transform {
+        Transform::_90 => image.rotate90(),
+        Transform::_180 => image.rotate180(),
+        Transform::_270 => image.rotate270(),
+        Transform::Flipped => image.fliph(),
+        Transform::Flipped90 => image.fliph().rotate90(),
+        Transform::Flipped180 => image.flipv(),
+        Transform::Flipped270 => image.fliph().rotate270(),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, GrayImage, Luma, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn output(name: &str, rect: Rect<i32>, scale: u32, color: [u8; 4]) -> OutputCapture {
+        OutputCapture {
+            name: name.to_string(),
+            rect,
+            image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+                rect.width as u32 * scale,
+                rect.height as u32 * scale,
+                Rgba(color),
+            )),
+        }
+    }
+
+    #[test]
+    fn test_crop() {
+        let capture = Capture {
+            outputs: vec![
+                output("eDP-1", Rect::new(0, 0, 4, 2), 2, [255, 0, 0, 255]),
+                output("DP-1", Rect::new(4, 0, 2, 2), 1, [0, 0, 255, 255]),
+            ],
+            time: Local::now(),
+            cursor: false,
+        };
+
+        // Natively from a single output
+        let crop = capture.crop(&Rect::new(1, 0, 2, 2), CropScale::Min);
+        assert_eq!(crop.dimensions(), (4, 4));
+
+        // Across outputs at the scale of the sharpest one
+        let crop = capture
+            .crop(&Rect::new(2, 0, 4, 2), CropScale::Max)
+            .to_rgba8();
+        assert_eq!(crop.dimensions(), (8, 4));
+        assert_eq!(crop.get_pixel(3, 3), &Rgba([255, 0, 0, 255]));
+        assert_eq!(crop.get_pixel(4, 0), &Rgba([0, 0, 255, 255]));
+
+        // Or of the least sharp one
+        let crop = capture
+            .crop(&Rect::new(2, 0, 4, 2), CropScale::Min)
+            .to_rgba8();
+        assert_eq!(crop.dimensions(), (4, 2));
+        assert_eq!(crop.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(crop.get_pixel(2, 1), &Rgba([0, 0, 255, 255]));
+
+        // Gaps between outputs stay transparent
+        let crop = capture
+            .crop(&Rect::new(4, 0, 4, 4), CropScale::Fixed(1.0))
+            .to_rgba8();
+        assert_eq!(crop.dimensions(), (4, 4));
+        assert_eq!(crop.get_pixel(3, 3), &Rgba([0, 0, 0, 0]));
+    }
+
+    /// The rows of the image after undoing the transform
+    fn transformed(transform: Transform) -> Vec<Vec<u8>> {
+        // 1 2 3
+        // 4 5 6
+        let image = GrayImage::from_fn(3, 2, |x, y| Luma([(y * 3 + x + 1) as u8]));
+        let image = to_logical(DynamicImage::ImageLuma8(image), transform).to_luma8();
+
+        image
+            .rows()
+            .map(|row| row.map(|Luma([value])| *value).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_transform_normal() {
+        assert_eq!(transformed(Transform::Normal), [[1, 2, 3], [4, 5, 6]]);
+    }
+
+    #[test]
+    fn test_transform_90() {
+        assert_eq!(transformed(Transform::_90), [[4, 1], [5, 2], [6, 3]]);
+    }
+
+    #[test]
+    fn test_transform_180() {
+        assert_eq!(transformed(Transform::_180), [[6, 5, 4], [3, 2, 1]]);
+    }
+
+    #[test]
+    fn test_transform_270() {
+        assert_eq!(transformed(Transform::_270), [[3, 6], [2, 5], [1, 4]]);
+    }
+
+    #[test]
+    fn test_transform_flipped() {
+        assert_eq!(transformed(Transform::Flipped), [[3, 2, 1], [6, 5, 4]]);
+    }
+
+    #[test]
+    fn test_transform_flipped_90() {
+        assert_eq!(transformed(Transform::Flipped90), [[6, 3], [5, 2], [4, 1]]);
+    }
+
+    #[test]
+    fn test_transform_flipped_180() {
+        assert_eq!(transformed(Transform::Flipped180), [[4, 5, 6], [1, 2, 3]]);
+    }
+
+    #[test]
+    fn test_transform_flipped_270() {
+        assert_eq!(transformed(Transform::Flipped270), [[1, 4], [2, 5], [3, 6]]);
+    }
+
+    #[test]
+    fn test_output_bookkeeping() {
+        let mut capture = Capture::default();
+        capture.insert_output(output("eDP-1", Rect::new(0, 0, 4, 2), 2, [255, 0, 0, 255]));
+        capture.insert_output(output("DP-1", Rect::new(4, 0, 2, 2), 1, [0, 0, 255, 255]));
+
+        // Capturing an output again replaces its screenshot
+        capture.insert_output(output("DP-1", Rect::new(4, 0, 2, 2), 2, [0, 255, 0, 255]));
+        assert_eq!(capture.outputs.len(), 2);
+        assert_eq!(
+            capture
+                .output(Some("DP-1"), &Rect::default())
+                .unwrap()
+                .scale(),
+            2.0
+        );
+
+        // Found by name, or by where it is without one
+        capture.move_output(Some("DP-1"), &Rect::default(), Rect::new(-2, 0, 2, 2));
+        assert_eq!(capture.output_at((-1, 1)).unwrap().name, "DP-1");
+        capture.move_output(None, &Rect::new(0, 0, 4, 2), Rect::new(0, 2, 4, 2));
+        assert_eq!(
+            capture.output(None, &Rect::new(0, 2, 4, 2)).unwrap().name,
+            "eDP-1"
+        );
+        assert!(capture.output_at((1, 1)).is_none());
+
+        capture.remove_output("eDP-1");
+        assert!(capture.output(Some("eDP-1"), &Rect::default()).is_none());
+        assert_eq!(capture.outputs.len(), 1);
+    }
+
+    #[test]
+    fn test_transform_scale() {
+        // A rotated output keeps its scale once the screenshot is upright again
+        let output = OutputCapture {
+            name: "DP-1".to_string(),
+            rect: Rect::new(0, 0, 1080, 1920),
+            image: to_logical(DynamicImage::new_rgba8(3840, 2160), Transform::_90),
+        };
+        assert_eq!(output.scale(), 2.0);
+    }
+}
@@ -0,0 +1,307 @@
+use image::{Rgba, RgbaImage};
+use smithay_client_toolkit::{
+    compositor::Region,
+    output::OutputInfo,
+    reexports::{
+        client::{
+            QueueHandle,
+            protocol::{wl_output, wl_shm, wl_surface},
+        },
+        protocols::wp::{
+            fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
+            viewporter::client::wp_viewport::WpViewport,
+        },
+    },
+    shell::{
+        WaylandSurface,
+        wlr_layer::{Anchor, KeyboardInteractivity, Layer, LayerSurface},
+    },
+    shm::slot::{Buffer, SlotPool},
+};
+use wgpu_text::glyph_brush::ab_glyph::FontArc;
+
+use crate::{
+    annotation::Text,
+    runtime_data::RuntimeData,
+    types::{Color, Config, Rect},
+};
+
+/// Height of the remaining seconds in logical pixels
+const COUNTDOWN_TEXT_SIZE: f32 = 96.0;
+/// Space around the remaining seconds in logical pixels
+const COUNTDOWN_PADDING: i32 = 16;
+
+/// Shows the seconds left until the capture on top of the pre-selected region.
+/// The surfaces take neither keyboard focus nor pointer input, so menus and
+/// tooltips stay open underneath.
+pub struct Countdown {
+    /// The pre-selected region in global coordinates
+    region: Rect<i32>,
+    surfaces: Vec<CountdownSurface>,
+    pool: SlotPool,
+}
+
+struct CountdownSurface {
+    layer: LayerSurface,
+    /// The part of the region on the output, in global coordinates
+    rect: Rect<i32>,
+    /// Fractional if the compositor supports it, like the overlay
+    scale: f32,
+    viewport: Option<WpViewport>,
+    fractional_scale: Option<WpFractionalScaleV1>,
+    configured: bool,
+    /// Kept alive while the compositor may still read from it
+    buffer: Option<Buffer>,
+}
+
+impl Drop for CountdownSurface {
+    fn drop(&mut self) {
+        if let Some(viewport) = &self.viewport {
+            viewport.destroy();
+        }
+        if let Some(fractional_scale) = &self.fractional_scale {
+            fractional_scale.destroy();
+        }
+    }
+}
+
+impl Countdown {
+    pub fn new(
+        region: Rect<i32>,
+        outputs: &[(Rect<i32>, wl_output::WlOutput, OutputInfo)],
+        qh: &QueueHandle<RuntimeData>,
+        runtime_data: &RuntimeData,
+    ) -> Self {
+        let surfaces =
+            outputs
+                .iter()
+                .filter_map(|(output_rect, output, info)| {
+                    let rect = region.intersection(output_rect)?;
+
+                    let wl_surface = runtime_data.compositor_state.create_surface(qh);
+                    let layer = runtime_data.layer_state.create_layer_surface(
+                        qh,
+                        wl_surface.clone(),
+                        Layer::Overlay,
+                        Some("watershot-countdown"),
+                        Some(output),
+                    );
+
+                    // Let the pointer through to whatever is underneath
+                    let input_region = Region::new(&runtime_data.compositor_state)
+                        .expect("wl_compositor is not available");
+                    wl_surface.set_input_region(Some(input_region.wl_region()));
+
+                    // The same way as the overlay, see `Monitor::new`
+                    let (viewport, fractional_scale) =
+                        match (
+                            &runtime_data.viewporter,
+                            &runtime_data.fractional_scale_manager,
+                        ) {
+                            (Some(viewporter), Some(fractional_scale_manager)) => {
+                                let viewport = viewporter.get_viewport(&wl_surface, qh, ());
+                                viewport.set_destination(rect.width, rect.height);
+
+                                let fractional_scale = fractional_scale_manager
+                                    .get_fractional_scale(&wl_surface, qh, wl_surface.clone());
+
+                                (Some(viewport), Some(fractional_scale))
+                            }
+                            _ => {
+                                wl_surface.set_buffer_scale(info.scale_factor);
+                                (None, None)
+                            }
+                        };
+
+                    layer.set_anchor(Anchor::TOP | Anchor::LEFT);
+                    layer.set_margin(rect.y - output_rect.y, 0, 0, rect.x - output_rect.x);
+                    layer.set_size(rect.width as u32, rect.height as u32);
+                    layer.set_exclusive_zone(-1);
+                    layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+                    layer.commit();
+
+                    Some(CountdownSurface {
+                        layer,
+                        rect,
+                        // Until the compositor reports the preferred fractional scale
+                        scale: info.scale_factor as f32,
+                        viewport,
+                        fractional_scale,
+                        configured: false,
+                        buffer: None,
+                    })
+                })
+                .collect();
+
+        let pool = SlotPool::new(
+            (region.width * region.height * 4) as usize,
+            &runtime_data.shm_state,
+        )
+        .expect("Failed to create the countdown buffer pool");
+
+        Self {
+            region,
+            surfaces,
+            pool,
+        }
+    }
+
+    /// Marks the surface as ready to be drawn, returns false if it isn't part of the countdown
+    pub fn configure(&mut self, layer: &LayerSurface) -> bool {
+        match self
+            .surfaces
+            .iter_mut()
+            .find(|surface| surface.layer == *layer)
+        {
+            Some(surface) => {
+                surface.configured = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Use the preferred fractional scale for the surface from the next draw on,
+    /// returns false if it isn't part of the countdown
+    pub fn set_scale(&mut self, wl_surface: &wl_surface::WlSurface, scale: f32) -> bool {
+        match self
+            .surfaces
+            .iter_mut()
+            .find(|surface| surface.layer.wl_surface() == wl_surface)
+        {
+            Some(surface) => {
+                surface.scale = scale;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Draw the outline of the region with the remaining seconds in its center
+    pub fn draw(&mut self, seconds: u32, config: &Config, font: &FontArc) {
+        for surface in self
+            .surfaces
+            .iter_mut()
+            .filter(|surface| surface.configured)
+        {
+            let scale = surface.scale;
+            let to_physical = |value: i32| (value as f32 * scale).round() as i32;
+
+            let (width, height) = surface.rect.physical_size(scale);
+            let mut image = RgbaImage::new(width, height);
+            let (width, height) = (width as i32, height as i32);
+
+            // Only the edges of the region itself, not where it continues on another output
+            let line_width = to_physical(config.line_width).max(1);
+            let local = Rect::new(
+                to_physical(self.region.x - surface.rect.x),
+                to_physical(self.region.y - surface.rect.y),
+                to_physical(self.region.width),
+                to_physical(self.region.height),
+            );
+            for edge in [
+                Rect::new(local.x, local.y, local.width, line_width),
+                Rect::new(local.x, local.y, line_width, local.height),
+                Rect::new(
+                    local.x,
+                    local.y + local.height - line_width,
+                    local.width,
+                    line_width,
+                ),
+                Rect::new(
+                    local.x + local.width - line_width,
+                    local.y,
+                    line_width,
+                    local.height,
+                ),
+            ] {
+                fill(&mut image, &edge, config.selection_color);
+            }
+
+            let text = Text {
+                x: 0,
+                y: 0,
+                size: COUNTDOWN_TEXT_SIZE,
+                content: seconds.to_string(),
+            };
+            let bounds = text.bounds(font);
+            let x = (width - to_physical(bounds.width)) / 2;
+            let y = (height - to_physical(bounds.height)) / 2;
+
+            let padding = to_physical(COUNTDOWN_PADDING);
+            fill(
+                &mut image,
+                &Rect::new(
+                    x - padding,
+                    y - padding,
+                    to_physical(bounds.width) + 2 * padding,
+                    to_physical(bounds.height) + 2 * padding,
+                ),
+                config.shade_color,
+            );
+            text.rasterize(
+                &mut image,
+                font,
+                x as f32,
+                y as f32,
+                scale,
+                config.text_color,
+            );
+
+            let (buffer, canvas) =
+                match self
+                    .pool
+                    .create_buffer(width, height, width * 4, wl_shm::Format::Argb8888)
+                {
+                    Ok(buffer) => buffer,
+                    Err(why) => {
+                        log::error!("Failed to create the countdown buffer: {}", why);
+                        continue;
+                    }
+                };
+
+            // Premultiplied BGRA in memory
+            for (pixel, Rgba([r, g, b, a])) in canvas.chunks_exact_mut(4).zip(image.pixels()) {
+                let premultiply = |c: &u8| (*c as u16 * *a as u16 / 255) as u8;
+                pixel.copy_from_slice(&[premultiply(b), premultiply(g), premultiply(r), *a]);
+            }
+
+            let wl_surface = surface.layer.wl_surface();
+            if let Err(why) = buffer.attach_to(wl_surface) {
+                log::error!("Failed to attach the countdown buffer: {}", why);
+                continue;
+            }
+            wl_surface.damage_buffer(0, 0, width, height);
+            surface.layer.commit();
+
+            surface.buffer = Some(buffer);
+        }
+    }
+}
+
+/// Fill the part of the rectangle inside the image with the color
+fn fill(image: &mut RgbaImage, rect: &Rect<i32>, color: Color) {
+    let Some(rect) = rect.intersection(&Rect::new(
+        0,
+        0,
+        image.width() as i32,
+        image.height() as i32,
+    )) else {
+        return;
+    };
+
+    for y in rect.y..rect.y + rect.height {
+        for x in rect.x..rect.x + rect.width {
+            image.put_pixel(
+                x as u32,
+                y as u32,
+                Rgba([
+                    (color.r * 255.0).round() as u8,
+                    (color.g * 255.0).round() as u8,
+                    (color.b * 255.0).round() as u8,
+                    (color.a * 255.0).round() as u8,
+                ]),
+            );
+        }
+    }
+}
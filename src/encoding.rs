@@ -0,0 +1,241 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use image::{
+    DynamicImage, ImageFormat, ImageResult,
+    codecs::{
+        avif::AvifEncoder,
+        jpeg::JpegEncoder,
+        png::{self, PngEncoder},
+        pnm::{PnmEncoder, PnmSubtype, SampleEncoding},
+        webp::WebPEncoder,
+    },
+};
+use serde::Deserialize;
+
+use crate::{metadata::Metadata, types::Config};
+
+/// Speed of the AVIF encoder from 1 (slowest) to 10, the default of `cavif`
+const AVIF_SPEED: u8 = 4;
+
+/// The format screenshots are saved, copied and printed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+pub enum Format {
+    Png,
+    #[value(alias = "jpg")]
+    Jpeg,
+    /// Always lossless
+    Webp,
+    Avif,
+    Ppm,
+}
+
+impl Format {
+    /// The format belonging to the extension of the path, if it's one of the supported ones
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let path = path.as_ref();
+
+        match ImageFormat::from_path(path).ok()? {
+            ImageFormat::Png => Some(Self::Png),
+            ImageFormat::Jpeg => Some(Self::Jpeg),
+            ImageFormat::WebP => Some(Self::Webp),
+            ImageFormat::Avif => Some(Self::Avif),
+            // Only pixmaps are written, not the other PNM subtypes like .pgm
+            ImageFormat::Pnm
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("ppm")) =>
+            {
+                Some(Self::Ppm)
+            }
+            _ => None,
+        }
+    }
+
+    /// The format to save to the path in, from its extension. Paths without one of
+    /// the supported extensions get the one of `fallback` appended, so the file
+    /// name never claims a different format than the content.
+    pub fn for_path(path: &Path, fallback: Self) -> (PathBuf, Self) {
+        match Self::from_path(path) {
+            Some(format) => (path.to_path_buf(), format),
+            None => {
+                let mut appended = path.as_os_str().to_owned();
+                appended.push(".");
+                appended.push(fallback.extension());
+
+                (appended.into(), fallback)
+            }
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
+            Self::Ppm => "ppm",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+            Self::Avif => "image/avif",
+            Self::Ppm => "image/x-portable-pixmap",
+        }
+    }
+}
+
+/// How hard the PNG encoder tries to shrink the file. The encoder only has these
+/// presets, not the numeric levels of zlib.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PngCompression {
+    Default,
+    Fast,
+    Best,
+}
+
+impl From<PngCompression> for png::CompressionType {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::Default => Self::Default,
+            PngCompression::Fast => Self::Fast,
+            PngCompression::Best => Self::Best,
+        }
+    }
+}
+
+/// The filter the PNG encoder runs over each row before compressing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PngFilter {
+    NoFilter,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Picks one of the others for each row
+    Adaptive,
+}
+
+impl From<PngFilter> for png::FilterType {
+    fn from(filter: PngFilter) -> Self {
+        match filter {
+            PngFilter::NoFilter => Self::NoFilter,
+            PngFilter::Sub => Self::Sub,
+            PngFilter::Up => Self::Up,
+            PngFilter::Avg => Self::Avg,
+            PngFilter::Paeth => Self::Paeth,
+            PngFilter::Adaptive => Self::Adaptive,
+        }
+    }
+}
+
+/// Encode the image in the format with the quality and compression from the config
+pub fn encode(
+    image: &DynamicImage,
+    format: Format,
+    config: &Config,
+    writer: impl Write,
+) -> ImageResult<()> {
+    match format {
+        Format::Png => image.write_with_encoder(PngEncoder::new_with_quality(
+            writer,
+            config.png_compression.into(),
+            config.png_filter.into(),
+        )),
+        // Neither has an alpha channel
+        Format::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(writer, config.jpeg_quality)),
+        Format::Ppm => DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(
+            PnmEncoder::new(writer).with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary)),
+        ),
+        Format::Webp => image.write_with_encoder(WebPEncoder::new_lossless(writer)),
+        Format::Avif => image.write_with_encoder(AvifEncoder::new_with_speed_quality(
+            writer,
+            AVIF_SPEED,
+            config.avif_quality,
+        )),
+    }
+}
+
+/// Encode the image into a new file at the path, with the metadata if the format supports it
+pub fn save(
+    image: &DynamicImage,
+    path: impl AsRef<Path>,
+    format: Format,
+    config: &Config,
+    metadata: Option<&Metadata>,
+) -> ImageResult<()> {
+    let mut encoded = Vec::new();
+    encode(image, format, config, &mut encoded)?;
+
+    if let Some(metadata) = metadata {
+        encoded = metadata.embed(encoded, format, image.color().has_alpha());
+    }
+    fs::write(path, encoded)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("shot.png"), Some(Format::Png));
+        assert_eq!(Format::from_path("shot.JPG"), Some(Format::Jpeg));
+        assert_eq!(Format::from_path("/tmp/shot.jpeg"), Some(Format::Jpeg));
+        assert_eq!(Format::from_path("shot.webp"), Some(Format::Webp));
+        assert_eq!(Format::from_path("shot.avif"), Some(Format::Avif));
+        assert_eq!(Format::from_path("shot.ppm"), Some(Format::Ppm));
+        assert_eq!(Format::from_path("shot.pgm"), None);
+        assert_eq!(Format::from_path("shot.pbm"), None);
+        assert_eq!(Format::from_path("shot.gif"), None);
+        assert_eq!(Format::from_path("shot"), None);
+    }
+
+    #[test]
+    fn test_format_for_path() {
+        assert_eq!(
+            Format::for_path(Path::new("shot.jpg"), Format::Png),
+            (PathBuf::from("shot.jpg"), Format::Jpeg)
+        );
+        assert_eq!(
+            Format::for_path(Path::new("/tmp/shot.foo"), Format::Png),
+            (PathBuf::from("/tmp/shot.foo.png"), Format::Png)
+        );
+        assert_eq!(
+            Format::for_path(Path::new("shot.pgm"), Format::Png),
+            (PathBuf::from("shot.pgm.png"), Format::Png)
+        );
+        assert_eq!(
+            Format::for_path(Path::new("shot"), Format::Webp),
+            (PathBuf::from("shot.webp"), Format::Webp)
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        let image = DynamicImage::new_rgba8(4, 4);
+        let config = Config::default();
+
+        for (format, magic) in [
+            (Format::Png, &b"\x89PNG"[..]),
+            (Format::Jpeg, &b"\xff\xd8\xff"[..]),
+            (Format::Webp, &b"RIFF"[..]),
+            (Format::Ppm, &b"P6"[..]),
+        ] {
+            let mut buf = Vec::new();
+            encode(&image, format, &config, &mut buf).unwrap();
+            assert!(buf.starts_with(magic), "{:?}", format);
+        }
+    }
+}
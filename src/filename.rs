@@ -0,0 +1,247 @@
+use std::{
+    fmt::Write,
+    fs::OpenOptions,
+    io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, TimeZone};
+
+use crate::window::WindowDescriptor;
+
+/// The names screenshots saved into a directory get by default
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// Longest a window class, title or output name gets in a file name, in characters
+const MAX_FIELD_LENGTH: usize = 64;
+
+/// What the screenshot shows, for the fields of the file name template
+pub struct FilenameFields<'a> {
+    /// How the area was picked, "rectangle", "display", "window" or "desktop"
+    pub mode: &'a str,
+    pub window: Option<&'a WindowDescriptor>,
+    /// The output the area starts on
+    pub output: Option<&'a str>,
+    /// Size of the image in pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Expands the file name template, without the extension.
+///
+/// Supports chrono's `%` sequences for the time, `{mode}`, `{class}`, `{title}`,
+/// `{output}`, `{width}` and `{height}`. Anything else in braces is kept as is,
+/// so `{n}` is left for [`create_unique_file`].
+pub fn expand_template<Tz: TimeZone>(
+    template: &str,
+    fields: &FilenameFields,
+    time: &DateTime<Tz>,
+) -> Result<String, String>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+
+        let value = match &rest[1..end] {
+            "mode" => Some(fields.mode.to_string()),
+            "class" => Some(
+                fields
+                    .window
+                    .map_or(String::new(), |window| sanitize(&window.class)),
+            ),
+            "title" => Some(
+                fields
+                    .window
+                    .map_or(String::new(), |window| sanitize(&window.title)),
+            ),
+            "output" => Some(fields.output.map_or(String::new(), sanitize)),
+            "width" => Some(fields.width.to_string()),
+            "height" => Some(fields.height.to_string()),
+            _ => None,
+        };
+
+        match value {
+            // Percent signs in titles aren't meant for chrono
+            Some(value) => expanded.push_str(&value.replace('%', "%%")),
+            None => expanded.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    let mut name = String::new();
+    write!(name, "{}", time.format(&expanded))
+        .map_err(|_| format!("Invalid file name template \"{}\"", template))?;
+
+    // Sequences like %D contain slashes, which would point into other directories
+    Ok(name.replace('/', "-"))
+}
+
+/// Create an empty file in the directory without overwriting an existing one and
+/// return its path. `{n}` in the name is replaced with the first free counter
+/// from 1, without it a counter is only appended if the name is taken. Creating
+/// the file claims the name, so screenshots saved at the same time can't collide.
+pub fn create_unique_file(directory: &Path, name: &str, extension: &str) -> io::Result<PathBuf> {
+    let name = if name.is_empty() { "screenshot" } else { name };
+    let counted = name.contains("{n}");
+
+    let path = |counter: u32| {
+        let name = if counted {
+            name.replace("{n}", &counter.to_string())
+        } else if counter == 0 {
+            name.to_string()
+        } else {
+            format!("{}_{}", name, counter)
+        };
+
+        directory.join(format!("{}.{}", name, extension))
+    };
+
+    for path in (u32::from(counted)..=u32::MAX).map(path) {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(why) if why.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(why) => return Err(why),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "Ran out of file names",
+    ))
+}
+
+/// Replaces characters that are either invalid in file names or trouble in
+/// shells and other file systems, and keeps the name from being hidden.
+/// Braces are replaced too, so a value can't turn into the `{n}` placeholder.
+fn sanitize(value: &str) -> String {
+    let sanitized = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '{' | '}' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(MAX_FIELD_LENGTH)
+        .collect::<String>();
+
+    sanitized.trim().trim_start_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::types::Rect;
+
+    #[test]
+    fn test_expand_template() {
+        let time = Utc.with_ymd_and_hms(2024, 3, 9, 14, 5, 30).unwrap();
+        let window = WindowDescriptor {
+            initial_title: String::new(),
+            title: "~/src: vim 100% <done>".to_string(),
+            initial_class: String::new(),
+            class: "Alacritty".to_string(),
+            rect: Some(Rect::new(0, 0, 100, 100)),
+        };
+        let fields = FilenameFields {
+            mode: "window",
+            window: Some(&window),
+            output: Some("DP-1"),
+            width: 1920,
+            height: 1080,
+        };
+
+        assert_eq!(
+            expand_template(DEFAULT_FILENAME_TEMPLATE, &fields, &time),
+            Ok("2024-03-09_14-05-30".to_string())
+        );
+        assert_eq!(
+            expand_template("{class} {title}_{mode}", &fields, &time),
+            Ok("Alacritty ~_src_ vim 100% _done__window".to_string())
+        );
+        assert_eq!(
+            expand_template("%D_{output}_{width}x{height}_{n}_{other", &fields, &time),
+            Ok("03-09-24_DP-1_1920x1080_{n}_{other".to_string())
+        );
+        assert!(expand_template("%Q", &fields, &time).is_err());
+
+        // A title can't add the counter
+        let braced = WindowDescriptor {
+            title: "foo {n}".to_string(),
+            ..window.clone()
+        };
+        let fields = FilenameFields {
+            window: Some(&braced),
+            ..fields
+        };
+        let name = expand_template("{title}", &fields, &time).unwrap();
+        assert_eq!(name, "foo _n_");
+        assert!(!name.contains("{n}"));
+
+        let fields = FilenameFields {
+            window: None,
+            output: None,
+            ..fields
+        };
+        assert_eq!(
+            expand_template("{class}{title}{output}", &fields, &time),
+            Ok(String::new())
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("../.hidden"), "_.hidden");
+        assert_eq!(sanitize("  .config  "), "config");
+        assert_eq!(sanitize("a\nb\0c"), "a_b_c");
+        assert_eq!(sanitize(&"x".repeat(100)).len(), MAX_FIELD_LENGTH);
+        assert_eq!(sanitize("foo {n}"), "foo _n_");
+    }
+
+    #[test]
+    fn test_create_unique_file() {
+        let directory = std::env::temp_dir().join(format!("watershot-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = create_unique_file(&directory, "shot", "png").unwrap();
+        assert_eq!(path, directory.join("shot.png"));
+        assert!(path.exists());
+        // The first file claimed the name
+        assert_eq!(
+            create_unique_file(&directory, "shot", "png").unwrap(),
+            directory.join("shot_1.png")
+        );
+
+        fs::write(directory.join("shot-1.png"), "").unwrap();
+        assert_eq!(
+            create_unique_file(&directory, "shot-{n}", "png").unwrap(),
+            directory.join("shot-2.png")
+        );
+        assert_eq!(
+            create_unique_file(&directory, "", "jpg").unwrap(),
+            directory.join("screenshot.jpg")
+        );
+
+        // Existing files are never truncated
+        fs::write(directory.join("kept.png"), "data").unwrap();
+        create_unique_file(&directory, "kept", "png").unwrap();
+        assert_eq!(fs::read(directory.join("kept.png")).unwrap(), b"data");
+
+        assert!(create_unique_file(&directory.join("missing"), "shot", "png").is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+use crate::types::Rect;
+
+/// The format slurp prints the selection in by default
+pub const DEFAULT_GEOMETRY_FORMAT: &str = "%x,%y %wx%h";
+
+/// Formats the selection in global coordinates like slurp does.
+///
+/// Supported sequences are `%x`, `%y`, `%w` and `%h` for the selection,
+/// `%X` and `%Y` for its position relative to the output, `%o` for the output
+/// name and `%%` for a literal percent sign. Anything else is kept as is.
+pub fn format_geometry(
+    format: &str,
+    rect: &Rect<i32>,
+    output: Option<(&str, &Rect<i32>)>,
+) -> String {
+    let mut formatted = String::with_capacity(format.len());
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('x') => formatted.push_str(&rect.x.to_string()),
+            Some('y') => formatted.push_str(&rect.y.to_string()),
+            Some('w') => formatted.push_str(&rect.width.to_string()),
+            Some('h') => formatted.push_str(&rect.height.to_string()),
+            Some('X') => {
+                let x = output.map_or(rect.x, |(_, output)| rect.x - output.x);
+                formatted.push_str(&x.to_string());
+            }
+            Some('Y') => {
+                let y = output.map_or(rect.y, |(_, output)| rect.y - output.y);
+                formatted.push_str(&y.to_string());
+            }
+            Some('o') => formatted.push_str(output.map_or("<unknown>", |(name, _)| name)),
+            Some('%') => formatted.push('%'),
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+
+    formatted
+}
+
+/// Parses a geometry in slurp's default format, e.g. "10,20 300x200"
+pub fn parse_geometry(s: &str) -> Result<Rect<i32>, String> {
+    let invalid = || {
+        format!(
+            "Invalid geometry \"{}\", it should be in the form of \"x,y widthxheight\"",
+            s
+        )
+    };
+
+    let (position, size) = s.trim().split_once(' ').ok_or_else(invalid)?;
+    let (x, y) = position.split_once(',').ok_or_else(invalid)?;
+    let (width, height) = size.trim().split_once('x').ok_or_else(invalid)?;
+
+    let rect = Rect {
+        x: x.parse().map_err(|_| invalid())?,
+        y: y.parse().map_err(|_| invalid())?,
+        width: width.parse().map_err(|_| invalid())?,
+        height: height.parse().map_err(|_| invalid())?,
+    };
+
+    if rect.width <= 0 || rect.height <= 0 {
+        return Err(invalid());
+    }
+
+    Ok(rect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_geometry() {
+        assert_eq!(
+            parse_geometry("-1920,20 300x200"),
+            Ok(Rect::new(-1920, 20, 300, 200))
+        );
+        assert_eq!(
+            parse_geometry("10,20 300x200\n"),
+            Ok(Rect::new(10, 20, 300, 200))
+        );
+        assert!(parse_geometry("10,20").is_err());
+        assert!(parse_geometry("10 20 300x200").is_err());
+        assert!(parse_geometry("10,20 0x200").is_err());
+    }
+
+    #[test]
+    fn test_format_geometry() {
+        let rect = Rect::new(1930, 20, 300, 200);
+        let output = Rect::new(1920, 0, 2560, 1440);
+
+        assert_eq!(
+            format_geometry(DEFAULT_GEOMETRY_FORMAT, &rect, None),
+            "1930,20 300x200"
+        );
+        assert_eq!(
+            format_geometry("%o: %X,%Y 100%% %q", &rect, Some(("DP-1", &output))),
+            "DP-1: 10,20 100% %q"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{
+    annotation::Annotation,
+    types::{RectangleSelection, Selection},
+};
+
+/// Upper bound of the undo steps kept around
+const MAX_HISTORY: usize = 100;
+
+/// The user editable state that can be undone
+#[derive(Clone, PartialEq)]
+pub struct Snapshot {
+    pub selection: Selection,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Snapshot {
+    /// Leaves out the state of in progress drags and texts without content,
+    /// so only actual edits compare unequal
+    pub fn new(selection: &Selection, annotations: &[Annotation]) -> Self {
+        let selection = match selection {
+            Selection::Rectangle(Some(selection)) => {
+                Selection::Rectangle(Some(RectangleSelection {
+                    modifier: None,
+                    active: false,
+                    ..*selection
+                }))
+            }
+            _ => selection.clone(),
+        };
+
+        let annotations = annotations
+            .iter()
+            .filter(|annotation| {
+                !matches!(annotation, Annotation::Text(text) if text.content.is_empty())
+            })
+            .cloned()
+            .collect();
+
+        Self {
+            selection,
+            annotations,
+        }
+    }
+}
+
+/// Undo and redo stacks of the selection and annotations
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// The state from before the edit in progress
+    pending: Option<Snapshot>,
+}
+
+impl History {
+    /// Remember the state from before an edit, unless an edit is already in progress
+    pub fn begin(&mut self, before: Snapshot) {
+        if self.pending.is_none() {
+            self.pending = Some(before);
+        }
+    }
+
+    /// Finish the edit in progress, adding an undo step if anything changed
+    pub fn commit(&mut self, after: &Snapshot) {
+        let Some(before) = self.pending.take() else {
+            return;
+        };
+
+        if before != *after {
+            if self.undo.len() == MAX_HISTORY {
+                self.undo.remove(0);
+            }
+
+            self.undo.push(before);
+            self.redo.clear();
+        }
+    }
+
+    /// Returns the state to restore, if there is anything to undo
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        self.pending = None;
+
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+
+        Some(previous)
+    }
+
+    /// Returns the state to restore, if there is anything to redo
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        self.pending = None;
+
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        annotation::{AnnotationTool, Annotations, tests::font},
+        types::{Config, SelectionModifier},
+    };
+
+    fn snapshot(x: i32) -> Snapshot {
+        let mut selection = RectangleSelection::new(x, x);
+        selection.extents.end_x += 10;
+        selection.extents.end_y += 10;
+
+        Snapshot::new(&Selection::Rectangle(Some(selection)), &[])
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = History::default();
+
+        history.begin(snapshot(0));
+        history.commit(&snapshot(1));
+        history.begin(snapshot(1));
+        history.commit(&snapshot(2));
+
+        assert!(history.undo(snapshot(2)) == Some(snapshot(1)));
+        assert!(history.undo(snapshot(1)) == Some(snapshot(0)));
+        assert!(history.undo(snapshot(0)).is_none());
+
+        assert!(history.redo(snapshot(0)) == Some(snapshot(1)));
+
+        // A new edit drops the steps that could be redone
+        history.begin(snapshot(1));
+        history.commit(&snapshot(3));
+        assert!(history.redo(snapshot(3)).is_none());
+        assert!(history.undo(snapshot(3)) == Some(snapshot(1)));
+    }
+
+    #[test]
+    fn test_undo_typed_word() {
+        let mut history = History::default();
+        let selection = Selection::Rectangle(None);
+        let config = Config::default();
+        let font = font();
+        let mut annotations = Annotations::default();
+
+        // Placing the text opens the edit, losing focus commits it
+        history.begin(Snapshot::new(&selection, &annotations.finished));
+        annotations.press(AnnotationTool::Text, 10, 10, &config, &font);
+        annotations.finish();
+        for c in "hello".chars() {
+            annotations.editing_text_mut().unwrap().content.push(c);
+        }
+        annotations.stop_editing();
+        history.commit(&Snapshot::new(&selection, &annotations.finished));
+
+        // The whole word is a single step
+        let current = Snapshot::new(&selection, &annotations.finished);
+        assert!(history.undo(current).unwrap().annotations.is_empty());
+        assert!(history.undo(Snapshot::new(&selection, &[])).is_none());
+    }
+
+    #[test]
+    fn test_unchanged_edit() {
+        let mut history = History::default();
+
+        // Grabbing the selection without moving it is not an edit
+        let mut grabbed = RectangleSelection::new(0, 0);
+        grabbed.extents.end_x += 10;
+        grabbed.extents.end_y += 10;
+        grabbed.modifier = Some(SelectionModifier::Center(5, 5, grabbed.extents));
+
+        history.begin(snapshot(0));
+        history.commit(&Snapshot::new(&Selection::Rectangle(Some(grabbed)), &[]));
+
+        assert!(history.undo(snapshot(0)).is_none());
+    }
+}
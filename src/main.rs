@@ -1,18 +1,39 @@
-use std::io::{self, Cursor, Write};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
 
-use chrono::Local;
-use clap::Parser;
-use image::{DynamicImage, ImageFormat};
-use log::{error, info};
+use countdown::Countdown;
+use encoding::Format;
+use filename::{DEFAULT_FILENAME_TEMPLATE, FilenameFields};
+use image::DynamicImage;
+use log::{error, info, warn};
+use metadata::Metadata;
 use runtime_data::RuntimeData;
 use rustix::runtime::{self, Fork};
-use smithay_client_toolkit::reexports::client::{Connection, globals::registry_queue_init};
-use traits::{Contains, ToLocal};
+use smithay_client_toolkit::{
+    output::OutputInfo,
+    reexports::client::{
+        Connection, EventQueue, QueueHandle, globals::registry_queue_init, protocol::wl_output,
+    },
+};
 use types::{Args, Config, ExitState, Monitor, Rect, SaveLocation, Selection};
 use wl_clipboard_rs::copy;
 
+mod annotation;
+mod capture;
+mod countdown;
+mod encoding;
+mod filename;
+mod geometry;
+mod history;
 mod macros;
+mod metadata;
 mod runtime_data;
+mod snapping;
 mod traits;
 mod types;
 
@@ -20,6 +41,7 @@ pub mod window;
 
 mod sctk_impls {
     mod compositor_handler;
+    mod fractional_scale_handler;
     mod keyboard_handler;
     mod layer_shell_handler;
     mod output_handler;
@@ -31,68 +53,181 @@ mod sctk_impls {
 mod rendering;
 
 fn main() {
-    let args = Args::parse();
+    let args = Args::parse_checked();
     env_logger::init();
 
-    if let Some(image) = gui(&args) {
-        // Save the file if an argument for that is present
-        if let Some(save_location) = &args.save {
-            match save_location {
-                SaveLocation::Path { path } => {
-                    if let Err(why) = image.save(path) {
-                        error!("Error saving image: {}", why);
-                    }
-                }
-                SaveLocation::Directory { path } => {
-                    let local = Local::now();
-                    if let Err(why) = image.save(
-                        local
-                            .format(&format!("{}/%Y-%m-%d_%H-%M-%S.png", path))
-                            .to_string(),
-                    ) {
-                        error!("Error saving image: {}", why);
-                    }
-                }
-            }
+    let Some((runtime_data, rect)) = gui(&args) else {
+        // Scripts picking a geometry need to know it was cancelled
+        if args.print_geometry.is_some() {
+            std::process::exit(1);
         }
+        return;
+    };
+
+    if let Some(format) = &args.print_geometry {
+        println!("{}", runtime_data.format_geometry(format, &rect));
+        return;
+    }
 
-        // Save the selected image into the buffer
-        let mut buf = Cursor::new(Vec::new());
-        image
-            .write_to(&mut buf, ImageFormat::Png)
-            .expect("Failed to write image to buffer as PNG");
+    let image = crop(&runtime_data, rect);
+    let config = &runtime_data.config;
+    let format = args.format.unwrap_or(config.format);
 
-        let buf = buf.into_inner();
+    // Save the file if an argument for that is present
+    if let Some(save_location) = &args.save {
+        save(&args, &runtime_data, save_location, rect, &image, format);
+    }
+
+    // Save the selected image into the buffer
+    let mut buf = Vec::new();
+    if args.stdout || args.copy {
+        encoding::encode(&image, format, config, &mut buf)
+            .expect("Failed to write image to buffer");
+    }
 
-        if args.stdout {
-            if let Err(why) = io::stdout().lock().write_all(&buf) {
-                error!("Failed to write image content to stdout: {}", why);
+    // Disconnect from the compositor before forking
+    drop(runtime_data);
+
+    if args.stdout {
+        if let Err(why) = io::stdout().lock().write_all(&buf) {
+            error!("Failed to write image content to stdout: {}", why);
+        }
+    }
+
+    // Fork to serve copy requests
+    if args.copy {
+        match unsafe { runtime::kernel_fork() } {
+            Ok(Fork::ParentOf(_)) => {
+                info!("Forked to serve copy requests")
+            }
+            Ok(Fork::Child(_)) => {
+                // Serve copy requests
+                let mut opts = copy::Options::new();
+                opts.foreground(true);
+                opts.copy(
+                    copy::Source::Bytes(buf.into_boxed_slice()),
+                    copy::MimeType::Specific(format.mime_type().to_string()),
+                )
+                .expect("Failed to serve copied image");
             }
+            Err(why) => println!("Failed to fork: {}", why),
         }
+    }
+}
 
-        // Fork to serve copy requests
-        if args.copy {
-            match unsafe { runtime::kernel_fork() } {
-                Ok(Fork::ParentOf(_)) => {
-                    info!("Forked to serve copy requests")
-                }
-                Ok(Fork::Child(_)) => {
-                    // Serve copy requests
-                    let mut opts = copy::Options::new();
-                    opts.foreground(true);
-                    opts.copy(
-                        copy::Source::Bytes(buf.into_boxed_slice()),
-                        copy::MimeType::Autodetect,
-                    )
-                    .expect("Failed to serve copied image");
+/// Save the image to the path or into the directory, with the metadata unless it's stripped
+fn save(
+    args: &Args,
+    runtime_data: &RuntimeData,
+    save_location: &SaveLocation,
+    rect: Rect<i32>,
+    image: &DynamicImage,
+    format: Format,
+) {
+    let config = &runtime_data.config;
+    let fields = filename_fields(args, runtime_data, &rect, image);
+    let time = runtime_data.capture.time;
+
+    let (path, format) = match save_location {
+        SaveLocation::Path { path } => {
+            let (saved_path, format) = Format::for_path(Path::new(path), format);
+            if saved_path != Path::new(path) {
+                warn!(
+                    "\"{}\" has no supported extension, saving as {}",
+                    path,
+                    saved_path.display()
+                );
+            }
+
+            (saved_path, format)
+        }
+        SaveLocation::Directory { path } => {
+            let name = filename::expand_template(&config.filename_template, &fields, &time)
+                .unwrap_or_else(|why| {
+                    error!("{}, using the default one", why);
+                    filename::expand_template(DEFAULT_FILENAME_TEMPLATE, &fields, &time)
+                        .expect("The default file name template is valid")
+                });
+
+            match filename::create_unique_file(Path::new(path), &name, format.extension()) {
+                Ok(path) => (path, format),
+                Err(why) => {
+                    error!("Failed to create a file in {}: {}", path, why);
+                    return;
                 }
-                Err(why) => println!("Failed to fork: {}", why),
             }
         }
+    };
+
+    let geometry = Rect {
+        x: rect.x + runtime_data.area.x,
+        y: rect.y + runtime_data.area.y,
+        ..rect
+    };
+    let metadata = Metadata {
+        time,
+        geometry,
+        output: runtime_data
+            .capture
+            .output_at((geometry.x, geometry.y))
+            .map(|output| (output.name.as_str(), output.scale())),
+        window: fields.window,
+    };
+    let metadata = Some(&metadata).filter(|_| !config.strip_metadata);
+
+    if let Err(why) = encoding::save(image, &path, format, config, metadata) {
+        error!("Error saving image: {}", why);
+
+        // Don't leave the file claimed for it behind empty
+        if matches!(save_location, SaveLocation::Directory { .. }) {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// What the file name template is filled with
+fn filename_fields<'a>(
+    args: &Args,
+    runtime_data: &'a RuntimeData,
+    rect: &Rect<i32>,
+    image: &DynamicImage,
+) -> FilenameFields<'a> {
+    let window = match &runtime_data.selection {
+        Selection::Window(Some(window)) => Some(window),
+        _ => None,
+    };
+
+    let mode = if args.desktop {
+        "desktop"
+    } else if args.output.is_some() {
+        "display"
+    } else if args.geometry.is_some() {
+        "rectangle"
+    } else {
+        match (&runtime_data.selection, window) {
+            (Selection::Display(_), _) => "display",
+            (_, Some(_)) => "window",
+            _ => "rectangle",
+        }
+    };
+
+    // The output the area starts on, the rectangle is relative to the area
+    let output = runtime_data
+        .capture
+        .output_at((rect.x + runtime_data.area.x, rect.y + runtime_data.area.y))
+        .map(|output| output.name.as_str());
+
+    FilenameFields {
+        mode,
+        window: window.filter(|_| mode == "window"),
+        output,
+        width: image.width(),
+        height: image.height(),
     }
 }
 
-fn gui(args: &Args) -> Option<DynamicImage> {
+/// Runs the interactive selection, returning the selected area relative to the area spanning all monitors
+fn gui(args: &Args) -> Option<(RuntimeData, Rect<i32>)> {
     let conn = Connection::connect_to_env();
     if conn.is_err() {
         log::error!(
@@ -105,7 +240,7 @@ fn gui(args: &Args) -> Option<DynamicImage> {
 
     let (globals, mut event_queue) = registry_queue_init(&conn).unwrap();
     let qh = event_queue.handle();
-    let mut runtime_data = RuntimeData::new(&qh, &globals, args.clone());
+    let mut runtime_data = RuntimeData::new(&conn, &qh, &globals, args.clone());
 
     // Fetch the outputs from the compositor
     event_queue.roundtrip(&mut runtime_data).unwrap();
@@ -115,20 +250,8 @@ fn gui(args: &Args) -> Option<DynamicImage> {
         .outputs()
         .map(|output| {
             let info = runtime_data.output_state.info(&output).unwrap();
-            let size = info
-                .logical_size
-                .map(|(w, h)| (w as u32, h as u32))
-                .expect("Can't determine monitor size!");
-            let pos = info
-                .logical_position
-                .expect("Can't determine monitor position!");
-
-            let rect = Rect {
-                x: pos.0,
-                y: pos.1,
-                width: size.0 as i32,
-                height: size.1 as i32,
-            };
+            let rect =
+                Monitor::logical_rect(&info).expect("Can't determine monitor position and size!");
 
             // Extend the area spanning all monitors with the current monitor
             runtime_data.area.extend(&rect);
@@ -136,13 +259,47 @@ fn gui(args: &Args) -> Option<DynamicImage> {
         })
         .collect::<Vec<_>>();
 
-    runtime_data.scale_factor = runtime_data.image.width() as f32 / runtime_data.area.width as f32;
+    if let Some(delay) = args.delay {
+        wait(
+            Duration::from_secs_f32(delay.max(0.0)),
+            args,
+            &mut runtime_data,
+            &sizes,
+            &qh,
+            &mut event_queue,
+        );
+        runtime_data.refresh_windows(args);
+    }
+
+    if let Err(why) = runtime_data.capture(args.cursor || runtime_data.config.cursor) {
+        error!(
+            "Could not capture the screen, screencopy is not supported by the compositor: {}",
+            why
+        );
+        std::process::exit(1);
+    }
+
+    // Capture right away without creating the layer surfaces
+    if let Some(rect) = direct_capture_rect(args, &runtime_data, &sizes) {
+        let rect = Rect {
+            x: rect.x - runtime_data.area.x,
+            y: rect.y - runtime_data.area.y,
+            ..rect
+        };
+
+        return Some((runtime_data, rect));
+    }
 
     for (rect, output, info) in sizes {
-        runtime_data
-            .monitors
-            .push(Monitor::new(rect, &qh, &conn, output, info, &runtime_data));
+        let name = info.name.clone();
+
+        match Monitor::new(rect, &qh, &conn, output, info, &runtime_data) {
+            Some(monitor) => runtime_data.monitors.push(monitor),
+            None => log::error!("No screenshot of output {:?}, leaving it out", name),
+        }
     }
+    // Outputs showing up from here on get their own layer surfaces
+    runtime_data.overlay_shown = true;
 
     event_queue.roundtrip(&mut runtime_data).unwrap();
 
@@ -150,34 +307,128 @@ fn gui(args: &Args) -> Option<DynamicImage> {
         event_queue.blocking_dispatch(&mut runtime_data).unwrap();
         match runtime_data.exit {
             ExitState::ExitOnly => return None,
-            ExitState::ExitWithSelection(rect) => {
-                let image = match runtime_data.monitors.into_iter().find_map(|mon| {
-                    if mon.rect.contains(&rect) {
-                        Some(mon)
-                    } else {
-                        None
-                    }
-                }) {
-                    Some(mon) => {
-                        let rect = rect.to_local(&mon.rect);
-                        mon.image.crop_imm(
-                            rect.x as u32,
-                            rect.y as u32,
-                            rect.width as u32,
-                            rect.height as u32,
-                        )
-                    }
-                    None => runtime_data.image.crop_imm(
-                        (rect.x as f32 * runtime_data.scale_factor) as u32,
-                        (rect.y as f32 * runtime_data.scale_factor) as u32,
-                        (rect.width as f32 * runtime_data.scale_factor) as u32,
-                        (rect.height as f32 * runtime_data.scale_factor) as u32,
-                    ),
-                };
-
-                return Some(image);
-            }
+            ExitState::ExitWithSelection(rect) => return Some((runtime_data, rect)),
             ExitState::None => (),
         }
     }
 }
+
+/// Waits before the screenshot is taken, counting down on top of the pre-selected region if there is one
+fn wait(
+    delay: Duration,
+    args: &Args,
+    runtime_data: &mut RuntimeData,
+    outputs: &[(Rect<i32>, wl_output::WlOutput, OutputInfo)],
+    qh: &QueueHandle<RuntimeData>,
+    event_queue: &mut EventQueue<RuntimeData>,
+) {
+    let end = Instant::now() + delay;
+
+    // Only shown when the region is going to be selected interactively
+    let direct_capture = args.geometry.is_some() || args.output.is_some() || args.desktop;
+    let region = runtime_data
+        .selection_rect()
+        .filter(|_| runtime_data.config.countdown && !args.auto_capture && !direct_capture);
+
+    let Some(region) = region else {
+        thread::sleep(delay);
+        return;
+    };
+
+    runtime_data.countdown = Some(Countdown::new(region, outputs, qh, runtime_data));
+    event_queue.roundtrip(runtime_data).unwrap();
+
+    loop {
+        let remaining = end.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        if let Some(countdown) = &mut runtime_data.countdown {
+            countdown.draw(
+                remaining.as_secs_f32().ceil() as u32,
+                &runtime_data.config,
+                &runtime_data.font,
+            );
+        }
+        event_queue.roundtrip(runtime_data).unwrap();
+
+        // Sleep until the next full second is left
+        let until_next = remaining.as_secs_f32().fract();
+        thread::sleep(if until_next > 0.0 {
+            Duration::from_secs_f32(until_next)
+        } else {
+            Duration::from_secs(1).min(remaining)
+        });
+    }
+
+    // Make sure the countdown is gone before the screenshot
+    runtime_data.countdown = None;
+    event_queue.roundtrip(runtime_data).unwrap();
+}
+
+/// The area in global coordinates to capture without the interactive selection, if any was requested
+fn direct_capture_rect(
+    args: &Args,
+    runtime_data: &RuntimeData,
+    outputs: &[(Rect<i32>, wl_output::WlOutput, OutputInfo)],
+) -> Option<Rect<i32>> {
+    if let Some(geometry) = args.geometry {
+        match geometry.constrain(&runtime_data.area) {
+            Some(rect) => Some(rect),
+            None => {
+                error!("The geometry {:?} is outside of all outputs", geometry);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(name) = &args.output {
+        match outputs
+            .iter()
+            .find(|(_, _, info)| info.name.as_ref() == Some(name))
+        {
+            Some((rect, _, _)) => Some(*rect),
+            None => {
+                error!("No output named \"{}\"", name);
+                std::process::exit(1);
+            }
+        }
+    } else if args.desktop {
+        Some(runtime_data.area)
+    } else if args.auto_capture {
+        // Falls back to the interactive selection if no window was found
+        match runtime_data.selection.flattened() {
+            Selection::Rectangle(Some(selection)) => Some(selection.extents.to_rect()),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// Crops the selection out of the screenshot and draws the annotations onto it
+fn crop(runtime_data: &RuntimeData, rect: Rect<i32>) -> DynamicImage {
+    // Annotations and outputs are in global coordinates
+    let global_rect = Rect {
+        x: rect.x + runtime_data.area.x,
+        y: rect.y + runtime_data.area.y,
+        ..rect
+    };
+
+    let mut image = runtime_data
+        .capture
+        .crop(&global_rect, runtime_data.config.crop_scale);
+
+    if !runtime_data.annotations.is_empty() {
+        let mut rgba = image.to_rgba8();
+
+        runtime_data.annotations.rasterize(
+            &mut rgba,
+            &global_rect,
+            &runtime_data.config,
+            Some(&runtime_data.font),
+        );
+        image = DynamicImage::ImageRgba8(rgba);
+    }
+
+    image
+}
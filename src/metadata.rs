@@ -0,0 +1,339 @@
+use chrono::{DateTime, Local};
+
+use crate::{
+    encoding::Format,
+    geometry::{DEFAULT_GEOMETRY_FORMAT, format_geometry},
+    types::Rect,
+    window::WindowDescriptor,
+};
+
+/// Namespace of the XMP properties without a standard one. A URN, so it doesn't
+/// depend on where the project is hosted.
+const XMP_NAMESPACE: &str = "urn:watershot:xmp:1.0:";
+/// Identifies an APP1 segment of a JPEG as XMP
+const JPEG_XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Largest payload of a JPEG segment, its length field counts itself
+const JPEG_MAX_SEGMENT_LENGTH: usize = u16::MAX as usize - 2;
+/// The PNG signature and the header chunk, which has to come first
+const PNG_HEADER_LENGTH: usize = 8 + 8 + 13 + 4;
+/// The RIFF header of a WebP file, before its first chunk
+const WEBP_HEADER_LENGTH: usize = 12;
+/// First byte of the data of a VP8L chunk
+const VP8L_SIGNATURE: u8 = 0x2f;
+/// Flags of the extended WebP header
+const WEBP_XMP_FLAG: u8 = 0x04;
+const WEBP_ALPHA_FLAG: u8 = 0x10;
+
+/// Where a screenshot came from, embedded into saved images for bug reports
+pub struct Metadata<'a> {
+    pub time: DateTime<Local>,
+    /// The selection in global logical coordinates
+    pub geometry: Rect<i32>,
+    /// Name and scale of the output the selection starts on
+    pub output: Option<(&'a str, f32)>,
+    /// The window, if it was captured in window mode
+    pub window: Option<&'a WindowDescriptor>,
+}
+
+impl Metadata<'_> {
+    /// The PNG keyword, the XMP property and the value of each entry
+    fn entries(&self) -> Vec<(&'static str, &'static str, String)> {
+        let mut entries = vec![
+            ("Creation Time", "xmp:CreateDate", self.time.to_rfc3339()),
+            (
+                "Software",
+                "xmp:CreatorTool",
+                format!("watershot {}", env!("CARGO_PKG_VERSION")),
+            ),
+            (
+                "Geometry",
+                "watershot:Geometry",
+                format_geometry(DEFAULT_GEOMETRY_FORMAT, &self.geometry, None),
+            ),
+        ];
+
+        if let Some((name, scale)) = self.output {
+            entries.push(("Output", "watershot:Output", name.to_string()));
+            entries.push(("Output Scale", "watershot:OutputScale", scale.to_string()));
+        }
+
+        if let Some(window) = self.window {
+            entries.push((
+                "Window Class",
+                "watershot:WindowClass",
+                window.class.clone(),
+            ));
+            entries.push((
+                "Window Title",
+                "watershot:WindowTitle",
+                window.title.clone(),
+            ));
+        }
+
+        entries
+    }
+
+    /// Add the metadata to the encoded image. PNG gets text chunks, JPEG and
+    /// WebP an XMP packet, the other formats are returned as they are.
+    pub fn embed(&self, encoded: Vec<u8>, format: Format, has_alpha: bool) -> Vec<u8> {
+        match format {
+            Format::Png => self.embed_png(encoded),
+            Format::Jpeg => self.embed_jpeg(encoded),
+            Format::Webp => self.embed_webp(encoded, has_alpha),
+            Format::Avif | Format::Ppm => encoded,
+        }
+    }
+
+    fn embed_png(&self, encoded: Vec<u8>) -> Vec<u8> {
+        let mut chunks = Vec::new();
+
+        for (keyword, _, value) in self.entries() {
+            let mut data = keyword.as_bytes().to_vec();
+            data.push(0);
+
+            // tEXt is Latin-1, so anything beyond ASCII goes into an iTXt chunk as UTF-8
+            let kind = if value.is_ascii() {
+                b"tEXt"
+            } else {
+                // No compression, no language tag and no translated keyword
+                data.extend_from_slice(&[0, 0, 0, 0]);
+                b"iTXt"
+            };
+            data.extend_from_slice(value.as_bytes());
+
+            chunks.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            let start = chunks.len();
+            chunks.extend_from_slice(kind);
+            chunks.extend_from_slice(&data);
+            let crc = crc32(&chunks[start..]);
+            chunks.extend_from_slice(&crc.to_be_bytes());
+        }
+
+        splice(encoded, PNG_HEADER_LENGTH, &chunks)
+    }
+
+    fn embed_jpeg(&self, encoded: Vec<u8>) -> Vec<u8> {
+        let xmp = self.xmp();
+        let length = JPEG_XMP_SIGNATURE.len() + xmp.len();
+        if length > JPEG_MAX_SEGMENT_LENGTH {
+            log::warn!("The metadata is too long for a JPEG segment, leaving it out");
+            return encoded;
+        }
+
+        let mut segment = vec![0xff, 0xe1];
+        segment.extend_from_slice(&(length as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(JPEG_XMP_SIGNATURE);
+        segment.extend_from_slice(xmp.as_bytes());
+
+        // After the start of image marker and the JFIF header, which have to come first
+        let mut offset = 2;
+        if encoded.get(2..4) == Some(&[0xff, 0xe0]) {
+            offset += 2 + u16::from_be_bytes([encoded[4], encoded[5]]) as usize;
+        }
+
+        splice(encoded, offset, &segment)
+    }
+
+    fn embed_webp(&self, encoded: Vec<u8>, has_alpha: bool) -> Vec<u8> {
+        // A simple lossless WebP is a single VP8L chunk after the RIFF header
+        let chunk_length = encoded
+            .get(16..20)
+            .map(|length| u32::from_le_bytes(length.try_into().unwrap()) as usize);
+        if encoded.get(0..4) != Some(b"RIFF")
+            || encoded.get(8..12) != Some(b"WEBP")
+            || encoded.get(12..16) != Some(b"VP8L")
+            || encoded.get(20) != Some(&VP8L_SIGNATURE)
+            || encoded.len() < WEBP_HEADER_LENGTH + 13
+            || chunk_length.map(|length| WEBP_HEADER_LENGTH + 8 + length + length % 2)
+                != Some(encoded.len())
+        {
+            log::warn!("Unexpected WebP layout, leaving out the metadata");
+            return encoded;
+        }
+
+        // The size is in the bits following the VP8L signature byte
+        let bits = u32::from_le_bytes([encoded[21], encoded[22], encoded[23], encoded[24]]);
+        let width_minus_one = bits & 0x3fff;
+        let height_minus_one = (bits >> 14) & 0x3fff;
+
+        let mut flags = WEBP_XMP_FLAG;
+        if has_alpha {
+            flags |= WEBP_ALPHA_FLAG;
+        }
+
+        let mut chunks = Vec::new();
+        chunks.extend_from_slice(b"VP8X");
+        chunks.extend_from_slice(&10u32.to_le_bytes());
+        chunks.extend_from_slice(&[flags, 0, 0, 0]);
+        chunks.extend_from_slice(&width_minus_one.to_le_bytes()[..3]);
+        chunks.extend_from_slice(&height_minus_one.to_le_bytes()[..3]);
+
+        chunks.extend_from_slice(&encoded[WEBP_HEADER_LENGTH..]);
+
+        let xmp = self.xmp();
+        chunks.extend_from_slice(b"XMP ");
+        chunks.extend_from_slice(&(xmp.len() as u32).to_le_bytes());
+        chunks.extend_from_slice(xmp.as_bytes());
+        // Chunks are padded to an even size
+        if xmp.len() % 2 == 1 {
+            chunks.push(0);
+        }
+
+        let mut webp = Vec::with_capacity(WEBP_HEADER_LENGTH + chunks.len());
+        webp.extend_from_slice(b"RIFF");
+        webp.extend_from_slice(&(4 + chunks.len() as u32).to_le_bytes());
+        webp.extend_from_slice(b"WEBP");
+        webp.extend_from_slice(&chunks);
+
+        webp
+    }
+
+    fn xmp(&self) -> String {
+        let properties = self
+            .entries()
+            .into_iter()
+            .map(|(_, property, value)| {
+                format!("   <{property}>{}</{property}>\n", escape_xml(&value))
+            })
+            .collect::<String>();
+
+        format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+             <rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" \
+             xmlns:watershot=\"{}\">\n\
+             {}  </rdf:Description>\n \
+             </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>",
+            XMP_NAMESPACE, properties
+        )
+    }
+}
+
+/// Insert the bytes at the offset
+fn splice(mut encoded: Vec<u8>, offset: usize, bytes: &[u8]) -> Vec<u8> {
+    encoded.splice(offset..offset, bytes.iter().copied());
+    encoded
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The CRC-32 PNG chunks end with, over their type and data
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use image::DynamicImage;
+
+    use super::*;
+    use crate::{encoding::encode, types::Config};
+
+    fn metadata(window: &WindowDescriptor) -> Metadata<'_> {
+        Metadata {
+            time: Local.timestamp_opt(1_700_000_000, 0).unwrap(),
+            geometry: Rect::new(-1920, 20, 300, 200),
+            output: Some(("DP-1", 1.5)),
+            window: Some(window),
+        }
+    }
+
+    fn window() -> WindowDescriptor {
+        WindowDescriptor {
+            initial_title: String::new(),
+            title: "Bug <report> – Firefox".to_string(),
+            initial_class: String::new(),
+            class: "firefox".to_string(),
+            rect: Some(Rect::new(-1920, 0, 1920, 1080)),
+        }
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn test_embed() {
+        let image = DynamicImage::new_rgba8(5, 3);
+        let config = Config::default();
+        let window = window();
+        let metadata = metadata(&window);
+
+        for format in [Format::Png, Format::Jpeg, Format::Webp] {
+            let mut encoded = Vec::new();
+            encode(&image, format, &config, &mut encoded).unwrap();
+
+            let embedded = metadata.embed(encoded, format, true);
+
+            // Still readable with the metadata in place
+            let decoded = image::load_from_memory(&embedded).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (5, 3), "{:?}", format);
+
+            if format == Format::Png {
+                assert!(contains(&embedded, b"tEXtGeometry\0-1920,20 300x200"));
+                assert!(contains(&embedded, b"tEXtOutput Scale\x001.5"));
+                assert!(contains(
+                    &embedded,
+                    "iTXtWindow Title\0\0\0\0\0Bug <report> – Firefox".as_bytes()
+                ));
+            } else {
+                assert!(contains(
+                    &embedded,
+                    b"<watershot:Geometry>-1920,20 300x200</watershot:Geometry>"
+                ));
+                assert!(contains(
+                    &embedded,
+                    "<watershot:WindowTitle>Bug &lt;report&gt; – Firefox".as_bytes()
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_embed_webp_unexpected_layout() {
+        let window = window();
+        let metadata = metadata(&window);
+
+        // A lossy WebP, which has a VP8 chunk instead
+        let mut lossy = b"RIFF\x16\0\0\0WEBPVP8 \x0a\0\0\0".to_vec();
+        lossy.extend_from_slice(&[0; 10]);
+        assert_eq!(metadata.embed(lossy.clone(), Format::Webp, false), lossy);
+
+        // A lossless one with more chunks after the image
+        let mut encoded = Vec::new();
+        encode(
+            &DynamicImage::new_rgba8(5, 3),
+            Format::Webp,
+            &Config::default(),
+            &mut encoded,
+        )
+        .unwrap();
+        encoded.extend_from_slice(b"EXIF\0\0\0\0");
+        assert_eq!(metadata.embed(encoded.clone(), Format::Webp, true), encoded);
+    }
+}
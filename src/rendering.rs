@@ -1,13 +1,19 @@
-use image::RgbaImage;
-use smithay_client_toolkit::output::OutputInfo;
+use std::ops::Range;
+
+use image::{DynamicImage, GenericImageView, RgbaImage};
 use wgpu::util::DeviceExt;
-use wgpu_text::glyph_brush::{HorizontalAlign, Layout, OwnedSection, OwnedText, VerticalAlign};
+use wgpu_text::glyph_brush::{
+    HorizontalAlign, Layout, OwnedSection, OwnedText, VerticalAlign,
+    ab_glyph::{Font, FontArc, ScaleFont},
+};
 
 use crate::{
+    annotation::{Annotation, Annotations, Blend, Redaction},
     handles,
     runtime_data::RuntimeData,
-    traits::{Padded, ToLocal, ToRender},
-    types::{Config, Monitor, Rect, Selection},
+    snapping::SnapGuides,
+    traits::{Contains, Padded, ToLocal, ToRender},
+    types::{Color, Config, Monitor, Rect, Selection},
 };
 
 use wayland_client::protocol::wl_surface;
@@ -25,10 +31,33 @@ pub const CIRCLE_EDGES: u32 = 64;
 // 3 indices per edge/triangle
 // 8 circles per selection highlight
 // 24 indices from the selection highlight rectangle
-const MAX_SEL_INDICES: u64 = CIRCLE_EDGES as u64 * 3 * 8 + 24;
+// 12 indices from the two snapping guides
+const MAX_SEL_INDICES: u64 = CIRCLE_EDGES as u64 * 3 * 8 + 24 + 12;
 
 const OVERLAY_MSAA: u32 = 4;
 
+/// Distance between the pointer and the magnifier, in logical pixels
+const MAGNIFIER_OFFSET: i32 = 24;
+/// Height of the coordinate and color readout below the magnifier, in logical pixels
+const MAGNIFIER_LABEL_HEIGHT: i32 = 20;
+
+/// Line height of the selection dimensions, in logical pixels
+const DIMENSIONS_TEXT_SIZE: f32 = 16.0;
+/// Distance between the selection and its dimensions, in logical pixels
+const DIMENSIONS_MARGIN: i32 = 6;
+
+/// Stencil of the highlighter strokes, a pixel passes while it's 0 and is then incremented
+const HIGHLIGHTER_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Stencil8;
+const HIGHLIGHTER_STENCIL_FACE: wgpu::StencilFaceState = wgpu::StencilFaceState {
+    compare: wgpu::CompareFunction::Equal,
+    fail_op: wgpu::StencilOperation::Keep,
+    depth_fail_op: wgpu::StencilOperation::Keep,
+    pass_op: wgpu::StencilOperation::IncrementClamp,
+};
+
+/// Initial amount of vertices and indices the annotation buffers can hold, they grow as needed
+const INITIAL_ANNOTATION_CAPACITY: u64 = 1024;
+
 pub struct Renderer {
     // Pipelines
     tex_pipeline: wgpu::RenderPipeline,
@@ -39,12 +68,25 @@ pub struct Renderer {
     overlay_pipeline: wgpu::RenderPipeline,
     shade_bind_group: wgpu::BindGroup,
     sel_bind_group: wgpu::BindGroup,
+    annotation_bind_group: wgpu::BindGroup,
+
+    /// Draws the highlighter strokes directly onto the background with multiply blending
+    highlighter_pipeline: wgpu::RenderPipeline,
+    highlighter_bind_group: wgpu::BindGroup,
+
+    /// Previews the redactions by drawing the background texture pixelated or blurred
+    redact_pipeline: wgpu::RenderPipeline,
+
+    magnifier_pipeline: wgpu::RenderPipeline,
 }
 
 /// Monitor specific rendering related items
 pub struct MonSpecificRendering {
     /// Bind group for the background texture
     bg_bind_group: wgpu::BindGroup,
+    /// Texels of the background texture per logical pixel along each axis, which
+    /// is the resolution of the capture rather than the scale of the surface
+    bg_scale: [f32; 2],
 
     shade_index_count: u32,
     shade_vertex_buffer: wgpu::Buffer,
@@ -54,6 +96,29 @@ pub struct MonSpecificRendering {
     sel_vertex_buffer: wgpu::Buffer,
     sel_index_buffer: wgpu::Buffer,
 
+    annotation_index_count: u32,
+    annotation_vertex_buffer: wgpu::Buffer,
+    annotation_index_buffer: wgpu::Buffer,
+
+    /// Indices of each highlighter stroke, drawn one at a time so a stroke only darkens
+    /// each pixel once where it overlaps itself
+    highlighter_strokes: Vec<Range<u32>>,
+    highlighter_vertex_buffer: wgpu::Buffer,
+    highlighter_index_buffer: wgpu::Buffer,
+
+    redact_vertex_count: u32,
+    redact_vertex_buffer: wgpu::Buffer,
+
+    magnifier_vertex_count: u32,
+    magnifier_vertex_buffer: wgpu::Buffer,
+    /// Pixel coordinates and color under the pointer
+    magnifier_section: Option<OwnedSection>,
+    /// Size and position of the selection
+    dimensions_section: Option<OwnedSection>,
+
+    /// Marks the pixels already darkened by the highlighter stroke being drawn
+    highlighter_stencil_tex: wgpu::TextureView,
+
     /// Texture to render the overlay with anti-aliasing
     ms_tex: wgpu::TextureView,
     /// The target to resolve to when rendering the multisampled overlay
@@ -65,6 +130,8 @@ pub struct MonSpecificRendering {
     rect_mode_section: OwnedSection,
     display_mode_section: OwnedSection,
     window_mode_section: OwnedSection,
+    /// The text annotations on this monitor
+    text_sections: Vec<OwnedSection>,
 }
 
 impl Renderer {
@@ -140,6 +207,92 @@ impl Renderer {
             cache: None,
         });
 
+        let redact_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Redaction shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../res/redact.wgsl").into()),
+        });
+
+        let redact_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Redaction pipeline"),
+            layout: Some(&tex_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &redact_shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[RedactVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &redact_shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        let magnifier_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Magnifier shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../res/magnifier.wgsl").into()),
+        });
+
+        let magnifier_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Magnifier pipeline"),
+            layout: Some(&tex_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &magnifier_shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[MagnifierVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &magnifier_shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
         let tex_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::Repeat,
             address_mode_v: wgpu::AddressMode::Repeat,
@@ -219,35 +372,95 @@ impl Renderer {
             cache: None,
         });
 
-        let shade_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Shade color uniform buffer"),
-            contents: bytemuck::cast_slice(&[config.shade_color]),
-            usage: wgpu::BufferUsages::UNIFORM,
-        });
-
-        let shade_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Shade bind group"),
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: shade_buffer.as_entire_binding(),
-            }],
-        });
-
-        let sel_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Selection color uniform buffer"),
-            contents: bytemuck::cast_slice(&[config.selection_color]),
-            usage: wgpu::BufferUsages::UNIFORM,
+        let highlighter_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Highlighter render pipeline"),
+            layout: Some(&overlay_layout),
+            vertex: wgpu::VertexState {
+                module: &color_shapes_shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[OverlayVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &color_shapes_shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    // Multiply the destination with the source color
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Dst,
+                            dst_factor: wgpu::BlendFactor::Zero,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // Each pixel is only drawn while its stencil is still 0, and then marked
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: HIGHLIGHTER_STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: HIGHLIGHTER_STENCIL_FACE,
+                    back: HIGHLIGHTER_STENCIL_FACE,
+                    read_mask: !0,
+                    write_mask: !0,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
         });
 
-        let sel_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Selection bind group"),
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: sel_buffer.as_entire_binding(),
-            }],
-        });
+        let shade_bind_group =
+            color_bind_group(device, &bind_group_layout, config.shade_color, "Shade");
+        let sel_bind_group = color_bind_group(
+            device,
+            &bind_group_layout,
+            config.selection_color,
+            "Selection",
+        );
+        let annotation_bind_group = color_bind_group(
+            device,
+            &bind_group_layout,
+            config.annotation_color,
+            "Annotation",
+        );
+        // The alpha is applied beforehand, as the blending only multiplies the colors
+        let highlighter = config.highlighter_color;
+        let highlighter_bind_group = color_bind_group(
+            device,
+            &bind_group_layout,
+            Color {
+                r: 1.0 - highlighter.a * (1.0 - highlighter.r),
+                g: 1.0 - highlighter.a * (1.0 - highlighter.g),
+                b: 1.0 - highlighter.a * (1.0 - highlighter.b),
+                a: 1.0,
+            },
+            "Highlighter",
+        );
 
         Self {
             tex_pipeline,
@@ -257,6 +470,11 @@ impl Renderer {
             overlay_pipeline,
             shade_bind_group,
             sel_bind_group,
+            annotation_bind_group,
+            highlighter_pipeline,
+            highlighter_bind_group,
+            redact_pipeline,
+            magnifier_pipeline,
         }
     }
 
@@ -272,7 +490,7 @@ impl Renderer {
         let Some(rendering) = &mut monitor.rendering else {
             return;
         };
-        // Render the screenshot as the background
+        // Render the screenshot as the background, with the redactions on top
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -292,6 +510,43 @@ impl Renderer {
             render_pass.set_vertex_buffer(0, self.tex_vertex_buffer.slice(..));
             render_pass.set_bind_group(0, &rendering.bg_bind_group, &[]);
             render_pass.draw(0..6, 0..1);
+
+            render_pass.set_pipeline(&self.redact_pipeline);
+            render_pass.set_vertex_buffer(0, rendering.redact_vertex_buffer.slice(..));
+            render_pass.draw(0..rendering.redact_vertex_count, 0..1);
+        }
+        // Every stroke gets a cleared stencil, so overlapping strokes still darken each other
+        for stroke in &rendering.highlighter_strokes {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: surface_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &rendering.highlighter_stencil_tex,
+                    depth_ops: None,
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&self.highlighter_pipeline);
+            render_pass.set_vertex_buffer(0, rendering.highlighter_vertex_buffer.slice(..));
+            render_pass.set_index_buffer(
+                rendering.highlighter_index_buffer.slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+            render_pass.set_bind_group(0, &self.highlighter_bind_group, &[]);
+            render_pass.set_stencil_reference(0);
+            render_pass.draw_indexed(stroke.clone(), 0, 0..1);
         }
         // Draw the shade to the multisampling texture
         {
@@ -318,7 +573,7 @@ impl Renderer {
             render_pass.set_bind_group(0, &self.shade_bind_group, &[]);
             render_pass.draw_indexed(0..rendering.shade_index_count, 0, 0..1);
         }
-        // Draw the selection outline to the multisampling texture, and resolve it to the resolve texture
+        // Draw the annotations and the selection outline to the multisampling texture, and resolve it to the resolve texture
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -335,6 +590,14 @@ impl Renderer {
                 occlusion_query_set: None,
             });
             render_pass.set_pipeline(&self.overlay_pipeline);
+            render_pass.set_vertex_buffer(0, rendering.annotation_vertex_buffer.slice(..));
+            render_pass.set_index_buffer(
+                rendering.annotation_index_buffer.slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+            render_pass.set_bind_group(0, &self.annotation_bind_group, &[]);
+            render_pass.draw_indexed(0..rendering.annotation_index_count, 0, 0..1);
+
             render_pass.set_vertex_buffer(0, rendering.sel_vertex_buffer.slice(..));
             render_pass.set_index_buffer(
                 rendering.sel_index_buffer.slice(..),
@@ -343,7 +606,7 @@ impl Renderer {
             render_pass.set_bind_group(0, &self.sel_bind_group, &[]);
             render_pass.draw_indexed(0..rendering.sel_index_count, 0, 0..1);
         }
-        // Draw the resolve target texture on top
+        // Draw the resolve target texture and the magnifier on top
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -363,15 +626,29 @@ impl Renderer {
             render_pass.set_vertex_buffer(0, self.tex_vertex_buffer.slice(..));
             render_pass.set_bind_group(0, &rendering.ms_bind_group, &[]);
             render_pass.draw(0..6, 0..1);
+
+            render_pass.set_pipeline(&self.magnifier_pipeline);
+            render_pass.set_vertex_buffer(0, rendering.magnifier_vertex_buffer.slice(..));
+            render_pass.set_bind_group(0, &rendering.bg_bind_group, &[]);
+            render_pass.draw(0..rendering.magnifier_vertex_count, 0..1);
         }
 
-        if let Some(section) = match selection {
+        let mode_section = match selection {
             Selection::Rectangle(None) => Some(&rendering.rect_mode_section),
             Selection::Display(None) => Some(&rendering.display_mode_section),
             Selection::Window(None) => Some(&rendering.window_mode_section),
             _ => None,
-        } {
-            rendering.brush.queue(device, queue, vec![section]).unwrap();
+        };
+        let sections: Vec<_> = rendering
+            .text_sections
+            .iter()
+            .chain(&rendering.magnifier_section)
+            .chain(&rendering.dimensions_section)
+            .chain(mode_section)
+            .collect();
+
+        if !sections.is_empty() {
+            rendering.brush.queue(device, queue, sections).unwrap();
 
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -396,7 +673,7 @@ impl Renderer {
 impl MonSpecificRendering {
     pub fn new(
         rect: &Rect<i32>,
-        info: &OutputInfo,
+        scale: f32,
         format: wgpu::TextureFormat,
         background: RgbaImage,
         runtime_data: &RuntimeData,
@@ -485,12 +762,71 @@ impl MonSpecificRendering {
             mapped_at_creation: false,
         });
 
+        let annotation_vertex_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: INITIAL_ANNOTATION_CAPACITY * std::mem::size_of::<OverlayVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let annotation_index_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: INITIAL_ANNOTATION_CAPACITY * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let highlighter_vertex_buffer =
+            runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: INITIAL_ANNOTATION_CAPACITY * std::mem::size_of::<OverlayVertex>() as u64,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let highlighter_index_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: INITIAL_ANNOTATION_CAPACITY * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let redact_vertex_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: INITIAL_ANNOTATION_CAPACITY * std::mem::size_of::<RedactVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let magnifier_vertex_buffer = runtime_data.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 6 * std::mem::size_of::<MagnifierVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (width, height) = rect.physical_size(scale);
+
         let ms_size = wgpu::Extent3d {
-            width: (rect.width * info.scale_factor) as u32,
-            height: (rect.height * info.scale_factor) as u32,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
+        let highlighter_stencil_tex = runtime_data
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: ms_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HIGHLIGHTER_STENCIL_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
         let ms_tex = runtime_data
             .device
             .create_texture(&wgpu::TextureDescriptor {
@@ -541,14 +877,11 @@ impl MonSpecificRendering {
 
         let brush = wgpu_text::BrushBuilder::using_font(runtime_data.font.clone()).build(
             &runtime_data.device,
-            (rect.width * info.scale_factor) as u32,
-            (rect.height * info.scale_factor) as u32,
+            width,
+            height,
             format,
         );
-        let pos = (
-            (rect.width * info.scale_factor) as f32 / 2.0,
-            (rect.height * info.scale_factor) as f32 / 2.0,
-        );
+        let pos = (width as f32 / 2.0, height as f32 / 2.0);
         let layout = Layout::default()
             .h_align(HorizontalAlign::Center)
             .v_align(VerticalAlign::Center);
@@ -556,7 +889,7 @@ impl MonSpecificRendering {
         let rect_mode_section = OwnedSection::default()
             .add_text(
                 OwnedText::new("RECTANGLE MODE")
-                    .with_scale((runtime_data.config.mode_text_size * info.scale_factor) as f32)
+                    .with_scale(runtime_data.config.mode_text_size as f32 * scale)
                     .with_color(runtime_data.config.text_color),
             )
             .with_layout(layout)
@@ -564,7 +897,7 @@ impl MonSpecificRendering {
         let display_mode_section = OwnedSection::default()
             .add_text(
                 OwnedText::new("DISPLAY MODE")
-                    .with_scale((runtime_data.config.mode_text_size * info.scale_factor) as f32)
+                    .with_scale(runtime_data.config.mode_text_size as f32 * scale)
                     .with_color(runtime_data.config.text_color),
             )
             .with_layout(layout)
@@ -573,7 +906,7 @@ impl MonSpecificRendering {
         let window_mode_section = OwnedSection::default()
             .add_text(
                 OwnedText::new("WINDOW MODE")
-                    .with_scale((runtime_data.config.mode_text_size * info.scale_factor) as f32)
+                    .with_scale(runtime_data.config.mode_text_size as f32 * scale)
                     .with_color(runtime_data.config.text_color),
             )
             .with_layout(layout)
@@ -581,10 +914,23 @@ impl MonSpecificRendering {
 
         Self {
             bg_bind_group,
+            bg_scale: [
+                background.width() as f32 / rect.width as f32,
+                background.height() as f32 / rect.height as f32,
+            ],
             shade_vertex_buffer,
             shade_index_buffer,
             sel_vertex_buffer,
             sel_index_buffer,
+            annotation_vertex_buffer,
+            annotation_index_buffer,
+            highlighter_vertex_buffer,
+            highlighter_index_buffer,
+            redact_vertex_buffer,
+            magnifier_vertex_buffer,
+            magnifier_section: None,
+            dimensions_section: None,
+            highlighter_stencil_tex,
             ms_tex,
             ms_resolve_target_tex,
             ms_bind_group,
@@ -592,8 +938,13 @@ impl MonSpecificRendering {
             rect_mode_section,
             display_mode_section,
             window_mode_section,
+            text_sections: Vec::new(),
             shade_index_count: 0,
             sel_index_count: 0,
+            annotation_index_count: 0,
+            highlighter_strokes: Vec::new(),
+            redact_vertex_count: 0,
+            magnifier_vertex_count: 0,
         }
     }
 
@@ -602,6 +953,7 @@ impl MonSpecificRendering {
         mon_rect: &Rect<i32>,
         wl_surface: &wl_surface::WlSurface,
         selection: &Selection,
+        snap_guides: &SnapGuides,
         config: &Config,
         queue: &wgpu::Queue,
     ) {
@@ -660,6 +1012,44 @@ impl MonSpecificRendering {
                             sel_indices.append(&mut indices);
                         }
 
+                        // Lines across the monitor along the edges the selection is snapped to
+                        let guide_width = config.line_width as f32 / 2.0;
+                        let vertical_guide = snap_guides
+                            .x
+                            .filter(|x| (mon_rect.x..=mon_rect.x + mon_rect.width).contains(x))
+                            .map(|x| {
+                                Rect::new(
+                                    (x - mon_rect.x) as f32 - guide_width / 2.0,
+                                    0.0,
+                                    guide_width,
+                                    mon_rect.height as f32,
+                                )
+                            });
+                        let horizontal_guide = snap_guides
+                            .y
+                            .filter(|y| (mon_rect.y..=mon_rect.y + mon_rect.height).contains(y))
+                            .map(|y| {
+                                Rect::new(
+                                    0.0,
+                                    (y - mon_rect.y) as f32 - guide_width / 2.0,
+                                    mon_rect.width as f32,
+                                    guide_width,
+                                )
+                            });
+
+                        for guide in vertical_guide.into_iter().chain(horizontal_guide) {
+                            let guide = guide.to_render(mon_rect.width, mon_rect.height);
+                            let offset = sel_vertices.len() as u32;
+
+                            sel_vertices.extend([
+                                [guide.x + guide.width, guide.y],
+                                [guide.x, guide.y],
+                                [guide.x, guide.y - guide.height],
+                                [guide.x + guide.width, guide.y - guide.height],
+                            ]);
+                            sel_indices.extend(RECT_INDICES.iter().map(|index| index + offset));
+                        }
+
                         self.shade_index_count = shade_indices.len() as u32;
                         self.sel_index_count = sel_indices.len() as u32;
 
@@ -727,6 +1117,317 @@ impl MonSpecificRendering {
             bytemuck::cast_slice(&shade_indices),
         );
     }
+
+    pub fn update_annotation_vertices(
+        &mut self,
+        mon_rect: &Rect<i32>,
+        scale_factor: f32,
+        annotations: &Annotations,
+        config: &Config,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let to_render = |vertices: Vec<[f32; 2]>| -> Vec<[f32; 2]> {
+            vertices
+                .into_iter()
+                .map(|[x, y]| {
+                    [x - mon_rect.x as f32, y - mon_rect.y as f32]
+                        .to_render(mon_rect.width, mon_rect.height)
+                })
+                .collect()
+        };
+
+        let (annotation_vertices, annotation_indices, _) =
+            annotations.to_vertices(Blend::Over, config);
+        let (highlighter_vertices, highlighter_indices, highlighter_strokes) =
+            annotations.to_vertices(Blend::Multiply, config);
+
+        self.annotation_index_count = annotation_indices.len() as u32;
+        self.highlighter_strokes = highlighter_strokes;
+
+        write_growing_buffer(
+            device,
+            queue,
+            &mut self.annotation_vertex_buffer,
+            bytemuck::cast_slice(&to_render(annotation_vertices)),
+        );
+        write_growing_buffer(
+            device,
+            queue,
+            &mut self.annotation_index_buffer,
+            bytemuck::cast_slice(&annotation_indices),
+        );
+        write_growing_buffer(
+            device,
+            queue,
+            &mut self.highlighter_vertex_buffer,
+            bytemuck::cast_slice(&to_render(highlighter_vertices)),
+        );
+        write_growing_buffer(
+            device,
+            queue,
+            &mut self.highlighter_index_buffer,
+            bytemuck::cast_slice(&highlighter_indices),
+        );
+
+        let redact_vertices: Vec<_> = annotations
+            .redactions()
+            .filter_map(|(redaction, region)| {
+                let region = region.intersection(mon_rect)?.to_local(mon_rect);
+                Some(RedactVertex::rect_vertices(
+                    &region,
+                    mon_rect,
+                    self.bg_scale,
+                    redaction,
+                    redaction.amount(config) * self.bg_scale[1],
+                ))
+            })
+            .flatten()
+            .collect();
+
+        self.redact_vertex_count = redact_vertices.len() as u32;
+
+        write_growing_buffer(
+            device,
+            queue,
+            &mut self.redact_vertex_buffer,
+            bytemuck::cast_slice(&redact_vertices),
+        );
+
+        // The brush works in physical pixels
+        self.text_sections = annotations
+            .iter()
+            .enumerate()
+            .filter_map(|(index, annotation)| match annotation {
+                Annotation::Text(text) => Some((index, text)),
+                _ => None,
+            })
+            .map(|(index, text)| {
+                let content = if annotations.editing == Some(index) {
+                    // Show a caret while typing
+                    format!("{}|", text.content)
+                } else {
+                    text.content.clone()
+                };
+
+                OwnedSection::default()
+                    .add_text(
+                        OwnedText::new(content)
+                            .with_scale(text.size * scale_factor)
+                            .with_color(config.annotation_color),
+                    )
+                    .with_layout(Layout::default_single_line())
+                    .with_screen_position((
+                        (text.x - mon_rect.x) as f32 * scale_factor,
+                        (text.y - mon_rect.y) as f32 * scale_factor,
+                    ))
+            })
+            .collect();
+    }
+
+    /// Show the magnifier next to the pointer if it is on this monitor, hide it for `None`
+    pub fn update_magnifier(
+        &mut self,
+        mon_rect: &Rect<i32>,
+        scale_factor: f32,
+        background: &DynamicImage,
+        pointer: Option<(i32, i32)>,
+        config: &Config,
+        queue: &wgpu::Queue,
+    ) {
+        let Some((global_x, global_y)) = pointer.filter(|pointer| mon_rect.contains(pointer))
+        else {
+            self.magnifier_vertex_count = 0;
+            self.magnifier_section = None;
+            return;
+        };
+
+        let (pointer_x, pointer_y) = (global_x - mon_rect.x, global_y - mon_rect.y);
+        let size = config.magnifier_size;
+        let height = size + MAGNIFIER_LABEL_HEIGHT;
+
+        // Keep the magnifier on the monitor by flipping it to the other side of the pointer
+        let mut x = pointer_x + MAGNIFIER_OFFSET;
+        if x + size > mon_rect.width {
+            x = pointer_x - MAGNIFIER_OFFSET - size;
+        }
+        let mut y = pointer_y + MAGNIFIER_OFFSET;
+        if y + height > mon_rect.height {
+            y = pointer_y - MAGNIFIER_OFFSET - height;
+        }
+        let (x, y) = (x.max(0), y.max(0));
+
+        // The screenshot may have a different resolution than the surface
+        let tex_scale = background.width() as f32 / mon_rect.width as f32;
+        let center = [
+            (pointer_x as f32 * tex_scale).floor(),
+            (pointer_y as f32 * tex_scale).floor(),
+        ];
+        let texels_per_logical = tex_scale / config.magnifier_zoom.max(1) as f32;
+        // The texel under the pointer ends up in the middle of the square above the label
+        let texel_origin = [
+            center[0] + 0.5 - size as f32 / 2.0 * texels_per_logical,
+            center[1] + 0.5 - size as f32 / 2.0 * texels_per_logical,
+        ];
+
+        let vertices = [[0, 0], [0, 1], [1, 0], [1, 1], [1, 0], [0, 1]].map(|[right, bottom]| {
+            let corner = [(x + right * size) as f32, (y + bottom * height) as f32];
+
+            MagnifierVertex {
+                position: corner.to_render(mon_rect.width, mon_rect.height),
+                texel: [
+                    texel_origin[0] + (right * size) as f32 * texels_per_logical,
+                    texel_origin[1] + (bottom * height) as f32 * texels_per_logical,
+                ],
+                local: [right as f32, bottom as f32],
+                center,
+                label: MAGNIFIER_LABEL_HEIGHT as f32 / height as f32,
+            }
+        });
+
+        self.magnifier_vertex_count = vertices.len() as u32;
+        queue.write_buffer(
+            &self.magnifier_vertex_buffer,
+            0,
+            bytemuck::cast_slice(&vertices),
+        );
+
+        // The position in the same global coordinates as the selection and its geometry
+        let (texel_x, texel_y) = (center[0] as u32, center[1] as u32);
+        let readout = if texel_x < background.width() && texel_y < background.height() {
+            let [r, g, b, _] = background.get_pixel(texel_x, texel_y).0;
+            format!("{}, {}  #{:02x}{:02x}{:02x}", global_x, global_y, r, g, b)
+        } else {
+            format!("{}, {}", global_x, global_y)
+        };
+
+        // The brush works in physical pixels
+        self.magnifier_section = Some(
+            OwnedSection::default()
+                .add_text(
+                    OwnedText::new(readout)
+                        .with_scale(MAGNIFIER_LABEL_HEIGHT as f32 * scale_factor * 0.75)
+                        .with_color(config.text_color),
+                )
+                .with_layout(
+                    Layout::default_single_line()
+                        .h_align(HorizontalAlign::Center)
+                        .v_align(VerticalAlign::Center),
+                )
+                .with_screen_position((
+                    (x * 2 + size) as f32 * scale_factor / 2.0,
+                    (y * 2 + size * 2 + MAGNIFIER_LABEL_HEIGHT) as f32 * scale_factor / 2.0,
+                )),
+        );
+    }
+
+    /// Label the part of the selection on this monitor with the size and
+    /// position of the whole selection
+    pub fn update_dimensions_label(
+        &mut self,
+        mon_rect: &Rect<i32>,
+        scale_factor: f32,
+        selection_rect: Option<Rect<i32>>,
+        config: &Config,
+        font: &FontArc,
+    ) {
+        let Some((selection, visible)) = selection_rect.and_then(|selection| {
+            let visible = selection.intersection(mon_rect)?.to_local(mon_rect);
+            Some((selection, visible))
+        }) else {
+            self.dimensions_section = None;
+            return;
+        };
+
+        let mut label = format!(
+            "{}x{}  {},{}",
+            selection.width, selection.height, selection.x, selection.y
+        );
+        // Only the size, the position in pixels depends on the scale of every output
+        // left and above of the selection
+        if scale_factor != 1.0 {
+            let physical = |value: i32| (value as f32 * scale_factor).round() as i32;
+
+            label.push_str(&format!(
+                "  ({}x{} px)",
+                physical(selection.width),
+                physical(selection.height),
+            ));
+        }
+
+        let scaled = font.as_scaled(DIMENSIONS_TEXT_SIZE);
+        let width = label
+            .chars()
+            .map(|c| scaled.h_advance(scaled.glyph_id(c)))
+            .sum::<f32>()
+            .ceil() as i32;
+        let height = scaled.height().ceil() as i32;
+
+        // Below the selection, above it if there is no room, or inside of it as a last resort
+        let mut y = visible.y + visible.height + DIMENSIONS_MARGIN;
+        if y + height > mon_rect.height {
+            y = visible.y - DIMENSIONS_MARGIN - height;
+        }
+        if y < 0 {
+            y = visible.y + DIMENSIONS_MARGIN;
+        }
+        let y = y.clamp(0, (mon_rect.height - height).max(0));
+        let x = visible.x.clamp(0, (mon_rect.width - width).max(0));
+
+        // The brush works in physical pixels
+        self.dimensions_section = Some(
+            OwnedSection::default()
+                .add_text(
+                    OwnedText::new(label)
+                        .with_scale(DIMENSIONS_TEXT_SIZE * scale_factor)
+                        .with_color(config.text_color),
+                )
+                .with_layout(Layout::default_single_line())
+                .with_screen_position((x as f32 * scale_factor, y as f32 * scale_factor)),
+        );
+    }
+}
+
+/// Create a bind group for drawing overlay shapes with a single color
+fn color_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    color: Color,
+    label: &str,
+) -> wgpu::BindGroup {
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} color uniform buffer", label)),
+        contents: bytemuck::cast_slice(&[color]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&format!("{} bind group", label)),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    })
+}
+
+/// Write the data to the start of the buffer, replacing it with a bigger one if it doesn't fit
+fn write_growing_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &mut wgpu::Buffer,
+    data: &[u8],
+) {
+    if data.len() as u64 > buffer.size() {
+        *buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (data.len() as u64).next_power_of_two(),
+            usage: buffer.usage(),
+            mapped_at_creation: false,
+        });
+    }
+
+    queue.write_buffer(buffer, 0, data);
 }
 
 #[derive(Clone, Copy)]
@@ -844,6 +1545,113 @@ impl TexVertex {
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct RedactVertex {
+    position: [f32; 2],
+    tex_pos: [f32; 2],
+    /// Left, top, right and bottom edge of the region in texels
+    bounds: [f32; 4],
+    /// 0 to pixelate, 1 to blur
+    mode: u32,
+    /// Block size or standard deviation of the blur in texels
+    amount: f32,
+}
+
+impl RedactVertex {
+    const ATTRS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+        2 => Float32x4,
+        3 => Uint32,
+        4 => Float32,
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRS,
+        }
+    }
+
+    /// Two triangles covering the region, which is local to the monitor. The
+    /// bounds are rounded to whole texels of the background, like in the export.
+    fn rect_vertices(
+        region: &Rect<i32>,
+        mon_rect: &Rect<i32>,
+        [scale_x, scale_y]: [f32; 2],
+        redaction: Redaction,
+        amount: f32,
+    ) -> [Self; 6] {
+        let (left, top) = (region.x as f32, region.y as f32);
+        let (right, bottom) = (left + region.width as f32, top + region.height as f32);
+
+        let bounds = [
+            left * scale_x,
+            top * scale_y,
+            right * scale_x,
+            bottom * scale_y,
+        ]
+        .map(f32::round);
+        let mode = match redaction {
+            Redaction::Pixelate => 0,
+            Redaction::Blur => 1,
+        };
+
+        [
+            [left, top],
+            [left, bottom],
+            [right, top],
+            [right, bottom],
+            [right, top],
+            [left, bottom],
+        ]
+        .map(|corner| Self {
+            position: corner.to_render(mon_rect.width, mon_rect.height),
+            tex_pos: [
+                corner[0] / mon_rect.width as f32,
+                corner[1] / mon_rect.height as f32,
+            ],
+            bounds,
+            mode,
+            amount,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MagnifierVertex {
+    position: [f32; 2],
+    /// Position in the background texture, in texels
+    texel: [f32; 2],
+    /// Position inside of the magnifier, from 0 to 1
+    local: [f32; 2],
+    /// The texel under the pointer
+    center: [f32; 2],
+    /// Part of the magnifier height taken by the label
+    label: f32,
+}
+
+impl MagnifierVertex {
+    const ATTRS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x2,
+        4 => Float32,
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRS,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct OverlayVertex {
@@ -1,34 +1,46 @@
 use std::fs;
 
 use fontconfig::Fontconfig;
-use image::DynamicImage;
 
-use libwayshot::WayshotConnection;
 use smithay_client_toolkit::{
     compositor::CompositorState,
     output::OutputState,
-    reexports::client::{
-        QueueHandle,
-        globals::GlobalList,
-        protocol::{wl_keyboard, wl_pointer},
+    reexports::{
+        client::{
+            Connection, QueueHandle,
+            globals::GlobalList,
+            protocol::{wl_keyboard, wl_output, wl_pointer, wl_surface},
+        },
+        protocols::wp::{
+            fractional_scale::v1::client::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+            viewporter::client::wp_viewporter::WpViewporter,
+        },
     },
     registry::RegistryState,
-    seat::{SeatState, pointer::ThemedPointer},
-    shell::wlr_layer::LayerShell,
+    seat::{SeatState, keyboard::Modifiers, pointer::ThemedPointer},
+    shell::wlr_layer::{LayerShell, LayerSurface},
     shm::Shm,
 };
 
 use crate::{
-    Config, Monitor, Rect, Selection, handles,
-    rendering::Renderer,
+    Config, Monitor, Rect, Selection,
+    annotation::Annotations,
+    capture::Capture,
+    countdown::Countdown,
+    geometry::format_geometry,
+    handles,
+    history::{History, Snapshot},
+    rendering::{MonSpecificRendering, Renderer},
+    snapping::{SnapEdges, SnapGuides},
     traits::{Contains, DistanceTo},
     types::{
-        Args, ExitState, MonitorIdentification, RectangleSelection, SelectionModifier,
-        SelectionState,
+        Args, AspectRatio, DisplaySelection, ExitState, Extents, KeyboardEdit,
+        MonitorIdentification, RectangleSelection, SelectionModifier, SelectionState, Size,
     },
     window::{
         CompositorBackend, FindWindowExt, InitializeBackend, WindowDescriptor,
-        hyprland::HyprlandBackend,
+        foreign_toplevel::ForeignToplevelBackend, hyprland::HyprlandBackend, niri::NiriBackend,
+        sway::SwayBackend,
     },
 };
 
@@ -41,21 +53,38 @@ pub struct RuntimeData {
     pub compositor_state: CompositorState,
     pub layer_state: LayerShell,
     pub shm_state: Shm,
+    /// Both are needed for fractional scales, integer scales are used without them
+    pub fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    pub viewporter: Option<WpViewporter>,
 
     // Devices
     pub keyboard: Option<wl_keyboard::WlKeyboard>,
     pub pointer: Option<wl_pointer::WlPointer>,
     pub themed_pointer: Option<ThemedPointer>,
+    pub modifiers: Modifiers,
+    /// Last known pointer position in global coordinates
+    pub pointer_position: Option<(i32, i32)>,
+    /// Ratio the selection is kept at while dragging it
+    pub aspect_ratio: Option<AspectRatio>,
+    /// Size of the selection following the pointer until it gets placed with a click
+    pub fixed_size: Option<Size>,
+    /// Edges the dragged selection is snapped to
+    pub snap_guides: SnapGuides,
+    /// Shown while waiting for the capture delay
+    pub countdown: Option<Countdown>,
+    /// Whether the layer surfaces were created, outputs announced before that are set up by `gui()`
+    pub overlay_shown: bool,
 
     /// Combined area of all monitors
     pub area: Rect<i32>,
-    /// The scale factor of the screenshot image
-    pub scale_factor: f32,
     pub selection: Selection,
+    pub annotations: Annotations,
+    pub history: History,
     pub monitors: Vec<Monitor>,
     pub config: Config,
     pub font: wgpu_text::glyph_brush::ab_glyph::FontArc,
-    pub image: DynamicImage,
+    /// The screenshots of the individual outputs
+    pub capture: Capture,
     pub exit: ExitState,
 
     pub instance: wgpu::Instance,
@@ -67,17 +96,30 @@ pub struct RuntimeData {
 
     pub compositor_backend: Option<Box<dyn CompositorBackend>>,
     pub windows: Vec<WindowDescriptor>,
+    /// Select the window under the pointer once it enters a surface, used when
+    /// the compositor backend can't report the cursor position
+    pub select_window_on_enter: bool,
 }
 
 impl RuntimeData {
-    pub fn get_preferred_backend() -> Option<Box<dyn CompositorBackend>> {
-        HyprlandBackend::try_new().ok()
+    pub fn get_preferred_backend(
+        conn: &Connection,
+        globals: &GlobalList,
+    ) -> Option<Box<dyn CompositorBackend>> {
+        HyprlandBackend::try_new()
+            .or_else(|_| SwayBackend::try_new())
+            .or_else(|_| NiriBackend::try_new())
+            // Fall back to the wayland protocols if no compositor specific IPC is available
+            .or_else(|_| ForeignToplevelBackend::try_new(conn, globals))
+            .ok()
     }
 
-    pub fn new(qh: &QueueHandle<Self>, globals: &GlobalList, mut args: Args) -> Self {
-        let wayshot_connection = WayshotConnection::new().unwrap();
-        let image = wayshot_connection.screenshot_all(false).unwrap();
-
+    pub fn new(
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        globals: &GlobalList,
+        args: Args,
+    ) -> Self {
         let config = Config::load().unwrap_or_default();
 
         let fc = Fontconfig::new().expect("Failed to init FontConfig");
@@ -111,54 +153,20 @@ impl RuntimeData {
         ))
         .unwrap();
 
-        let compositor_backend = Self::get_preferred_backend();
+        let compositor_backend = Self::get_preferred_backend(conn, globals);
 
         let mut selection = Selection::default();
         let mut windows = Vec::default();
-        let mut exit = ExitState::None;
+        let mut select_window_on_enter = false;
 
         if let Some(ref compositor_backend) = compositor_backend {
-            (selection, windows, exit) = {
-                let windows = compositor_backend.get_all_windows();
-
-                let selection = {
-                    match args.window_search.take() {
-                        Some(search_param) => Selection::from_window(
-                            windows.find_by_search_param(search_param).cloned(),
-                        ),
-                        _ => {
-                            if args.window_under_cursor {
-                                let mouse_pos = compositor_backend.get_mouse_position();
-                                Selection::from_window(
-                                    windows.find_by_position(&mouse_pos).cloned(),
-                                )
-                            } else if args.active_window {
-                                Selection::from_window(compositor_backend.get_focused())
-                            } else {
-                                Selection::default()
-                            }
-                        }
-                    }
-                };
-
-                if !args.auto_capture {
-                    (selection, windows, ExitState::None)
-                } else {
-                    match selection.flattened() {
-                        Selection::Rectangle(Some(rect_sel)) => (
-                            selection,
-                            windows,
-                            ExitState::ExitWithSelection(rect_sel.extents.to_rect()),
-                        ),
-                        _ => {
-                            // TODO: Auto-capture for monitors
-                            (selection, windows, ExitState::None)
-                        }
-                    }
-                }
-            };
+            windows = compositor_backend.get_all_windows();
+            (selection, select_window_on_enter) =
+                Self::preselect_window(compositor_backend.as_ref(), &windows, &args);
         }
 
+        let aspect_ratio = args.aspect_ratio.or(config.aspect_ratio);
+
         RuntimeData {
             registry_state: RegistryState::new(globals),
             seat_state: SeatState::new(globals, qh),
@@ -166,17 +174,27 @@ impl RuntimeData {
             compositor_state,
             layer_state: LayerShell::bind(globals, qh).expect("layer shell is not available"),
             shm_state: Shm::bind(globals, qh).expect("wl_shm is not available"),
+            fractional_scale_manager: globals.bind(qh, 1..=1, ()).ok(),
+            viewporter: globals.bind(qh, 1..=1, ()).ok(),
             selection,
+            annotations: Annotations::default(),
+            history: History::default(),
             config,
             area: Rect::default(),
             monitors: Vec::new(),
-            // Set later
-            scale_factor: 0.0,
-            image,
+            // Captured once the outputs are known and the delay has passed
+            capture: Capture::default(),
             keyboard: None,
             pointer: None,
             themed_pointer: None,
-            exit,
+            modifiers: Modifiers::default(),
+            pointer_position: None,
+            aspect_ratio,
+            fixed_size: args.size,
+            snap_guides: SnapGuides::default(),
+            countdown: None,
+            overlay_shown: false,
+            exit: ExitState::None,
             instance,
             adapter,
             device,
@@ -188,25 +206,354 @@ impl RuntimeData {
             .expect("Invalid font data"),
             compositor_backend,
             windows,
+            select_window_on_enter,
+        }
+    }
+
+    /// The window the arguments ask for, and whether it should be picked once
+    /// the pointer enters a surface because the compositor can't tell where it is
+    fn preselect_window(
+        compositor_backend: &dyn CompositorBackend,
+        windows: &Vec<WindowDescriptor>,
+        args: &Args,
+    ) -> (Selection, bool) {
+        if let Some(search_param) = args.window_search.clone() {
+            (
+                Selection::from_window(windows.find_by_search_param(search_param).cloned()),
+                false,
+            )
+        } else if args.window_under_cursor {
+            match compositor_backend.get_mouse_position() {
+                Some(mouse_pos) => (
+                    Selection::from_window(windows.find_by_position(&mouse_pos).cloned()),
+                    false,
+                ),
+                // Without an overlay there is no pointer to wait for, and the cursor
+                // is usually over the focused window anyway
+                None if args.auto_capture => {
+                    log::warn!(
+                        "The compositor doesn't report the cursor position, \
+                         capturing the focused window instead of the one under the cursor"
+                    );
+                    (
+                        Selection::from_window(compositor_backend.get_focused()),
+                        false,
+                    )
+                }
+                // The window is picked once the pointer enters the overlay
+                None => (Selection::Window(None), true),
+            }
+        } else if args.active_window {
+            (
+                Selection::from_window(compositor_backend.get_focused()),
+                false,
+            )
+        } else {
+            (Selection::default(), false)
+        }
+    }
+
+    /// Fetch the windows again and redo the pre-selection, they may have moved
+    /// or closed while waiting for the delay
+    pub fn refresh_windows(&mut self, args: &Args) {
+        let Some(compositor_backend) = &self.compositor_backend else {
+            return;
+        };
+
+        self.windows = compositor_backend.get_all_windows();
+        (self.selection, self.select_window_on_enter) =
+            Self::preselect_window(compositor_backend.as_ref(), &self.windows, args);
+    }
+
+    /// Capture an output that got plugged in and show the overlay on it
+    pub fn add_monitor(
+        &mut self,
+        output: wl_output::WlOutput,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let Some(info) = self.output_state.info(&output) else {
+            return;
+        };
+        let Some(rect) = Monitor::logical_rect(&info) else {
+            log::warn!(
+                "Can't determine the position and size of output {:?}",
+                info.name
+            );
+            return;
+        };
+
+        if !self.capture.add_output(info.name.as_deref(), &rect) {
+            log::error!("Failed to capture output {:?}, leaving it out", info.name);
+            return;
+        }
+
+        log::info!("Output {:?} added at {:?}", info.name, rect);
+
+        let Some(monitor) = Monitor::new(rect, qh, conn, output, info, self) else {
+            return;
+        };
+        self.monitors.push(monitor);
+        self.update_area();
+    }
+
+    /// Follow an output that moved, or changed its size, scale or transform. Moving
+    /// keeps the screenshot taken before, any other change captures the output again.
+    pub fn update_monitor(
+        &mut self,
+        output: &wl_output::WlOutput,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let Some(info) = self.output_state.info(output) else {
+            return;
+        };
+        let Some(rect) = Monitor::logical_rect(&info) else {
+            return;
+        };
+        let Some(monitor) = self
+            .monitors
+            .iter_mut()
+            .find(|monitor| monitor.output == *output)
+        else {
+            return;
+        };
+
+        if rect == monitor.rect
+            && info.scale_factor == monitor.output_info.scale_factor
+            && info.transform == monitor.output_info.transform
+        {
+            return;
+        }
+
+        log::info!("Output {:?} changed to {:?}", info.name, rect);
+
+        // What the output shows is laid out again, so the old screenshot doesn't match
+        if rect.width != monitor.rect.width
+            || rect.height != monitor.rect.height
+            || info.scale_factor != monitor.output_info.scale_factor
+            || info.transform != monitor.output_info.transform
+        {
+            self.recapture_monitor(output, conn, qh);
+            return;
+        }
+
+        self.capture
+            .move_output(info.name.as_deref(), &monitor.rect, rect);
+        monitor.rect = rect;
+        monitor.output_info = info;
+
+        if monitor.rendering.is_some() {
+            let layer = monitor.layer.clone();
+            self.configure_monitor(&layer, qh);
+        }
+        self.update_area();
+    }
+
+    /// Capture the output again and show a new overlay with the screenshot on it
+    fn recapture_monitor(
+        &mut self,
+        output: &wl_output::WlOutput,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let Some(index) = self
+            .monitors
+            .iter()
+            .position(|monitor| monitor.output == *output)
+        else {
+            return;
+        };
+
+        // The old overlay has to be gone from the output, so it isn't captured itself
+        let monitor = self.monitors.remove(index);
+        let selected = matches!(
+            &self.selection,
+            Selection::Display(Some(selection)) if selection.wl_surface == monitor.wl_surface
+        );
+        if let Some(name) = &monitor.output_info.name {
+            self.capture.remove_output(name);
+        }
+        drop(monitor);
+
+        if let Err(why) = conn.flush() {
+            log::warn!("Failed to remove the overlay before capturing: {}", why);
+        }
+
+        self.add_monitor(output.clone(), conn, qh);
+        self.update_area();
+
+        if selected {
+            self.selection = Selection::Display(
+                self.monitors
+                    .iter()
+                    .find(|monitor| monitor.output == *output)
+                    .map(|monitor| DisplaySelection::new(monitor.wl_surface.clone())),
+            );
+        }
+    }
+
+    /// Drop the monitor of an unplugged output along with everything referring to it
+    pub fn remove_monitor(&mut self, output: &wl_output::WlOutput) {
+        let Some(index) = self
+            .monitors
+            .iter()
+            .position(|monitor| monitor.output == *output)
+        else {
+            return;
+        };
+
+        let monitor = self.monitors.remove(index);
+        log::info!("Output {:?} removed", monitor.output_info.name);
+
+        if let Some(name) = &monitor.output_info.name {
+            self.capture.remove_output(name);
+        }
+
+        match &self.selection {
+            Selection::Display(Some(selection)) if selection.wl_surface == monitor.wl_surface => {
+                self.selection = Selection::Display(None);
+            }
+            _ => (),
+        }
+
+        self.update_area();
+
+        // Nothing of the selection is left to capture
+        if let Some(rect) = self.selection_rect() {
+            if !self
+                .monitors
+                .iter()
+                .any(|monitor| monitor.rect.intersects(&rect))
+            {
+                self.selection = match self.selection {
+                    Selection::Rectangle(_) => Selection::Rectangle(None),
+                    Selection::Display(_) => Selection::Display(None),
+                    Selection::Window(_) => Selection::Window(None),
+                };
+            }
+        }
+    }
+
+    /// Span the area over the current monitors
+    fn update_area(&mut self) {
+        self.area = Rect::default();
+
+        for monitor in &self.monitors {
+            self.area.extend(&monitor.rect);
+        }
+    }
+
+    /// Configure the wgpu surface of the monitor at its physical size and set up its rendering
+    pub fn configure_monitor(&mut self, layer: &LayerSurface, qh: &QueueHandle<Self>) {
+        let Some(monitor) = self.monitors.iter().find(|window| window.layer == *layer) else {
+            return;
+        };
+
+        let cap = monitor.surface.get_capabilities(&self.adapter);
+
+        if self.renderer.is_none() {
+            self.renderer = Some(Renderer::new(&self.device, &self.config, cap.formats[0]));
+        }
+
+        let (width, height) = monitor.rect.physical_size(monitor.scale);
+
+        monitor.surface.configure(
+            &self.device,
+            &wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: cap.formats[0],
+                width,
+                height,
+                present_mode: wgpu::PresentMode::Mailbox,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![cap.formats[0]],
+                desired_maximum_frame_latency: 2,
+            },
+        );
+
+        let mon_rendering = MonSpecificRendering::new(
+            &monitor.rect,
+            monitor.scale,
+            cap.formats[0],
+            monitor.image.to_rgba8(),
+            self,
+        );
+
+        // Reborrow mutably to set the renderer
+        let monitor = self
+            .monitors
+            .iter_mut()
+            .find(|window| window.layer == *layer)
+            .unwrap();
+
+        monitor.rendering = Some(mon_rendering);
+
+        log::info!("{:?}", cap.formats);
+
+        self.draw(MonitorIdentification::Layer(layer.clone()), qh);
+    }
+
+    /// Apply the fractional scale preferred by the compositor, reconfiguring the
+    /// monitor if it was already set up at another scale
+    pub fn set_monitor_scale(
+        &mut self,
+        surface: &wl_surface::WlSurface,
+        scale: f32,
+        qh: &QueueHandle<Self>,
+    ) {
+        let Some(monitor) = self
+            .monitors
+            .iter_mut()
+            .find(|monitor| monitor.wl_surface == *surface)
+        else {
+            return;
+        };
+
+        if monitor.scale == scale {
+            return;
+        }
+
+        log::info!(
+            "Preferred scale of {:?}: {}",
+            monitor.output_info.name,
+            scale
+        );
+        monitor.scale = scale;
+
+        if monitor.rendering.is_some() {
+            let layer = monitor.layer.clone();
+            self.configure_monitor(&layer, qh);
         }
     }
 
     pub fn draw(&mut self, identification: MonitorIdentification, qh: &QueueHandle<Self>) {
+        let selection_rect = self.selection_rect();
+
         let Some(renderer) = &mut self.renderer else {
             return;
         };
 
-        let monitor = match identification {
+        // The magnifier helps to place the edges while dragging them
+        let magnified_position = match &self.selection {
+            Selection::Rectangle(Some(selection)) if self.config.magnifier && selection.active => {
+                self.pointer_position
+            }
+            _ => None,
+        };
+
+        // The monitor may have been removed in the meantime
+        let Some(monitor) = (match identification {
             MonitorIdentification::Layer(layer) => self
                 .monitors
                 .iter_mut()
-                .find(|window| window.layer == layer)
-                .unwrap(),
+                .find(|window| window.layer == layer),
             MonitorIdentification::Surface(surface) => self
                 .monitors
                 .iter_mut()
-                .find(|window| window.wl_surface == surface)
-                .unwrap(),
+                .find(|window| window.wl_surface == surface),
+        }) else {
+            return;
         };
 
         if let Some(rendering) = &mut monitor.rendering {
@@ -214,9 +561,33 @@ impl RuntimeData {
                 &monitor.rect,
                 &monitor.wl_surface,
                 &self.selection,
+                &self.snap_guides,
                 &self.config,
                 &self.queue,
             );
+            rendering.update_annotation_vertices(
+                &monitor.rect,
+                monitor.scale,
+                &self.annotations,
+                &self.config,
+                &self.device,
+                &self.queue,
+            );
+            rendering.update_magnifier(
+                &monitor.rect,
+                monitor.scale,
+                &monitor.image,
+                magnified_position,
+                &self.config,
+                &self.queue,
+            );
+            rendering.update_dimensions_label(
+                &monitor.rect,
+                monitor.scale,
+                selection_rect,
+                &self.config,
+                &self.font,
+            );
         }
 
         let surface_texture = monitor.surface.get_current_texture().unwrap();
@@ -247,6 +618,138 @@ impl RuntimeData {
         monitor.wl_surface.commit();
     }
 
+    /// The area covered by the current selection, in global coordinates
+    pub fn selection_rect(&self) -> Option<Rect<i32>> {
+        match self.selection.flattened() {
+            Selection::Rectangle(Some(selection)) => Some(selection.extents.to_rect()),
+            Selection::Display(Some(selection)) => self
+                .monitors
+                .iter()
+                .find(|monitor| monitor.wl_surface == selection.wl_surface)
+                .map(|monitor| monitor.rect),
+            _ => None,
+        }
+    }
+
+    /// Move or resize the selection by `step` in the direction of an arrow key, turning
+    /// a window or display selection into a rectangle. Without a selection, growing
+    /// starts a square of `step` at the pointer position.
+    pub fn edit_selection(&mut self, direction: (i32, i32), edit: KeyboardEdit, step: i32) {
+        let extents = match (self.selection_rect(), self.pointer_position) {
+            (Some(rect), _) => rect.to_extents(),
+            // Already as large as the step across the arrow, so growing gives it a size
+            (None, Some((x, y))) if edit == KeyboardEdit::Grow => Extents {
+                start_x: x,
+                start_y: y,
+                end_x: if direction.0 == 0 { x + step } else { x },
+                end_y: if direction.1 == 0 { y + step } else { y },
+            },
+            _ => return,
+        };
+
+        let rect = edit.apply(extents.to_rect(), direction, step, &self.area);
+
+        self.selection = Selection::Rectangle(Some(RectangleSelection {
+            extents: rect.to_extents(),
+            modifier: None,
+            active: false,
+        }));
+    }
+
+    /// Take the screenshots of all outputs, before the overlay is shown so the
+    /// cursor can be captured in place of the crosshair
+    pub fn capture(&mut self, cursor: bool) -> libwayshot::Result<()> {
+        self.capture = Capture::take(cursor)?;
+        Ok(())
+    }
+
+    /// Formats the selection relative to the area like slurp, see [`format_geometry`]
+    pub fn format_geometry(&self, format: &str, rect: &Rect<i32>) -> String {
+        let rect = Rect {
+            x: rect.x + self.area.x,
+            y: rect.y + self.area.y,
+            ..*rect
+        };
+
+        // The output the selection starts on, from the capture as there are no
+        // monitors when capturing directly
+        let output = self
+            .capture
+            .output_at((rect.x, rect.y))
+            .map(|output| (output.name.as_str(), &output.rect));
+
+        format_geometry(format, &rect, output)
+    }
+
+    /// Window borders and monitor edges the selection can snap to
+    pub fn snap_edges(&self) -> SnapEdges {
+        SnapEdges::new(
+            self.windows
+                .iter()
+                .filter_map(|window| window.rect.as_ref())
+                .chain(self.monitors.iter().map(|monitor| &monitor.rect)),
+        )
+    }
+
+    /// Center the fixed size selection on the position, keeping it inside the area
+    pub fn place_fixed_size_selection(&mut self, size: Size, (x, y): (i32, i32)) {
+        let extents = Extents {
+            start_x: x - size.width / 2,
+            start_y: y - size.height / 2,
+            end_x: x - size.width / 2 + size.width.min(self.area.width),
+            end_y: y - size.height / 2 + size.height.min(self.area.height),
+        };
+
+        self.selection = Selection::Rectangle(Some(RectangleSelection {
+            extents: extents.to_rect_clamped(&self.area).to_extents(),
+            modifier: None,
+            active: false,
+        }));
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::new(&self.selection, &self.annotations.finished)
+    }
+
+    /// Remember the state before the selection or the annotations get edited
+    pub fn begin_edit(&mut self) {
+        self.history.begin(self.snapshot());
+    }
+
+    /// Add an undo step for the edit started with [`Self::begin_edit`], if anything changed
+    pub fn end_edit(&mut self) {
+        let snapshot = self.snapshot();
+        self.history.commit(&snapshot);
+    }
+
+    /// Stop typing into the text annotation, committing the edit that started
+    /// when it was placed or picked up
+    pub fn stop_editing_text(&mut self) {
+        self.annotations.stop_editing();
+        self.end_edit();
+    }
+
+    pub fn undo(&mut self) {
+        self.stop_editing_text();
+
+        if let Some(snapshot) = self.history.undo(self.snapshot()) {
+            self.restore(snapshot);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.stop_editing_text();
+
+        if let Some(snapshot) = self.history.redo(self.snapshot()) {
+            self.restore(snapshot);
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.selection = snapshot.selection;
+        self.annotations.restore(snapshot.annotations);
+    }
+
     pub fn process_selection_handles(
         rect_sel: &mut Option<RectangleSelection>,
         global_pos: (i32, i32),
@@ -0,0 +1,95 @@
+use smithay_client_toolkit::reexports::{
+    client::{Connection, Dispatch, QueueHandle, protocol::wl_surface},
+    protocols::wp::{
+        fractional_scale::v1::client::{
+            wp_fractional_scale_manager_v1::{self, WpFractionalScaleManagerV1},
+            wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+        },
+        viewporter::client::{
+            wp_viewport::{self, WpViewport},
+            wp_viewporter::{self, WpViewporter},
+        },
+    },
+};
+
+use crate::runtime_data::RuntimeData;
+
+/// The preferred scale is sent as a fraction with this denominator
+const FRACTIONAL_SCALE_DENOMINATOR: f32 = 120.0;
+
+fn preferred_scale(numerator: u32) -> f32 {
+    numerator as f32 / FRACTIONAL_SCALE_DENOMINATOR
+}
+
+impl Dispatch<WpFractionalScaleV1, wl_surface::WlSurface> for RuntimeData {
+    fn event(
+        state: &mut Self,
+        _: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        surface: &wl_surface::WlSurface,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            let scale = preferred_scale(scale);
+
+            if let Some(countdown) = &mut state.countdown {
+                if countdown.set_scale(surface, scale) {
+                    return;
+                }
+            }
+
+            state.set_monitor_scale(surface, scale, qh);
+        }
+    }
+}
+
+// The remaining objects have no events
+impl Dispatch<WpFractionalScaleManagerV1, ()> for RuntimeData {
+    fn event(
+        _: &mut Self,
+        _: &WpFractionalScaleManagerV1,
+        _: wp_fractional_scale_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WpViewporter, ()> for RuntimeData {
+    fn event(
+        _: &mut Self,
+        _: &WpViewporter,
+        _: wp_viewporter::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WpViewport, ()> for RuntimeData {
+    fn event(
+        _: &mut Self,
+        _: &WpViewport,
+        _: wp_viewport::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preferred_scale() {
+        assert_eq!(preferred_scale(120), 1.0);
+        assert_eq!(preferred_scale(150), 1.25);
+        assert_eq!(preferred_scale(180), 1.5);
+        assert_eq!(preferred_scale(240), 2.0);
+    }
+}
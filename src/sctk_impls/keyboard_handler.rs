@@ -9,8 +9,9 @@ use smithay_client_toolkit::{
 };
 
 use crate::{
+    annotation::AnnotationTool,
     runtime_data::RuntimeData,
-    types::{ExitState, Selection},
+    types::{ExitState, KeyboardEdit, Selection},
 };
 
 delegate_keyboard!(RuntimeData);
@@ -46,53 +47,98 @@ impl KeyboardHandler for RuntimeData {
         _: u32,
         event: KeyEvent,
     ) {
+        // Ctrl+Z undoes, Ctrl+Shift+Z redoes
+        if self.modifiers.ctrl && matches!(event.keysym, Keysym::z | Keysym::Z) {
+            if self.modifiers.shift {
+                self.redo();
+            } else {
+                self.undo();
+            }
+            return;
+        }
+
+        // Keys are typed into the text annotation while one is being edited, the
+        // edit opened when it was placed stays open until it loses focus
+        if self.annotations.editing.is_some() {
+            match event.keysym {
+                Keysym::Escape | Keysym::Return => self.stop_editing_text(),
+                Keysym::BackSpace => {
+                    if let Some(text) = self.annotations.editing_text_mut() {
+                        text.content.pop();
+                    }
+                }
+                _ => {
+                    let utf8 = event
+                        .utf8
+                        .filter(|utf8| !utf8.chars().any(char::is_control));
+
+                    if let (Some(text), Some(utf8)) = (self.annotations.editing_text_mut(), utf8) {
+                        text.content.push_str(&utf8);
+                    }
+                }
+            }
+            return;
+        }
+
         match event.keysym {
             // Exit without copying/saving
             Keysym::Escape => self.exit = ExitState::ExitOnly,
             // Switch selection mode
-            Keysym::Tab => match &self.selection {
-                Selection::Rectangle(_) => self.selection = Selection::Display(None),
-                Selection::Display(_) => {
-                    if self.compositor_backend.is_some() {
-                        self.selection = Selection::Window(None)
-                    } else {
-                        self.selection = Selection::Rectangle(None)
-                    }
-                }
-                Selection::Window(_) => self.selection = Selection::Rectangle(None),
-            },
-            // Exit with save if a valid selection exists
-            Keysym::Return => {
-                let flattened_selection = self.selection.flattened();
-                match flattened_selection {
-                    Selection::Rectangle(Some(selection)) => {
-                        let mut rect = selection.extents.to_rect();
-                        // Alter coordinate space so the rect can be used to crop from the original image
-                        rect.x -= self.area.x;
-                        rect.y -= self.area.y;
+            Keysym::Tab => {
+                self.begin_edit();
 
-                        self.exit = ExitState::ExitWithSelection(rect)
+                match &self.selection {
+                    Selection::Rectangle(_) => self.selection = Selection::Display(None),
+                    Selection::Display(_) => {
+                        // Only if there is a window that can be selected
+                        if self.windows.iter().any(|window| window.rect.is_some()) {
+                            self.selection = Selection::Window(None)
+                        } else {
+                            self.selection = Selection::Rectangle(None)
+                        }
                     }
-                    Selection::Display(Some(selection)) => {
-                        let monitor = self
-                            .monitors
-                            .iter()
-                            .find(|monitor| monitor.wl_surface == selection.wl_surface)
-                            .unwrap();
-
-                        let mut rect = monitor.rect;
+                    Selection::Window(_) => self.selection = Selection::Rectangle(None),
+                }
 
-                        rect.x -= self.area.x;
-                        rect.y -= self.area.y;
+                self.end_edit();
+            }
+            // Exit with save if a valid selection exists, an empty one can't be captured
+            Keysym::Return => {
+                if let Some(mut rect) = self
+                    .selection_rect()
+                    .filter(|rect| rect.width > 0 && rect.height > 0)
+                {
+                    // Alter coordinate space so the rect can be used to crop from the original image
+                    rect.x -= self.area.x;
+                    rect.y -= self.area.y;
 
-                        self.exit = ExitState::ExitWithSelection(rect)
-                    }
-                    Selection::Window(_) => unreachable!(
-                        "Window selection should have been flattened into Rectangle selection"
-                    ),
-                    _ => (),
+                    self.exit = ExitState::ExitWithSelection(rect)
                 }
             }
+            // Move the selection, or resize it while Shift or Ctrl is held
+            Keysym::Left | Keysym::Right | Keysym::Up | Keysym::Down => {
+                let direction = match event.keysym {
+                    Keysym::Left => (-1, 0),
+                    Keysym::Right => (1, 0),
+                    Keysym::Up => (0, -1),
+                    _ => (0, 1),
+                };
+                let (edit, step) = KeyboardEdit::from_modifiers(&self.modifiers);
+
+                self.begin_edit();
+                self.edit_selection(direction, edit, step);
+                self.end_edit();
+            }
+            // Annotation tools
+            Keysym::r => self.annotations.toggle_tool(AnnotationTool::Rectangle),
+            Keysym::e => self.annotations.toggle_tool(AnnotationTool::Ellipse),
+            Keysym::l => self.annotations.toggle_tool(AnnotationTool::Line),
+            Keysym::a => self.annotations.toggle_tool(AnnotationTool::Arrow),
+            Keysym::p => self.annotations.toggle_tool(AnnotationTool::Pen),
+            Keysym::h => self.annotations.toggle_tool(AnnotationTool::Highlighter),
+            Keysym::t => self.annotations.toggle_tool(AnnotationTool::Text),
+            Keysym::x => self.annotations.toggle_tool(AnnotationTool::Pixelate),
+            Keysym::b => self.annotations.toggle_tool(AnnotationTool::Blur),
             _ => (),
         }
     }
@@ -118,5 +164,6 @@ impl KeyboardHandler for RuntimeData {
         _layout: u32,
     ) {
         info!("Update modifiers: {:?}", modifiers);
+        self.modifiers = modifiers;
     }
 }
@@ -5,11 +5,7 @@ use smithay_client_toolkit::{
     shell::wlr_layer::{LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
 };
 
-use crate::{
-    rendering::{MonSpecificRendering, Renderer},
-    runtime_data::RuntimeData,
-    types::MonitorIdentification,
-};
+use crate::runtime_data::RuntimeData;
 
 delegate_layer!(RuntimeData);
 
@@ -24,6 +20,13 @@ impl LayerShellHandler for RuntimeData {
         _configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
+        // The countdown surfaces are drawn on their own
+        if let Some(countdown) = &mut self.countdown {
+            if countdown.configure(layer) {
+                return;
+            }
+        }
+
         let _ = self
             .themed_pointer
             .as_ref()
@@ -32,51 +35,6 @@ impl LayerShellHandler for RuntimeData {
 
         log::info!("{:?}", _configure);
 
-        let monitor = self
-            .monitors
-            .iter()
-            .find(|window| window.layer == *layer)
-            .unwrap();
-
-        let cap = monitor.surface.get_capabilities(&self.adapter);
-
-        if self.renderer.is_none() {
-            self.renderer = Some(Renderer::new(&self.device, &self.config, cap.formats[0]));
-        }
-
-        monitor.surface.configure(
-            &self.device,
-            &wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                format: cap.formats[0],
-                width: (monitor.rect.width * monitor.output_info.scale_factor) as u32,
-                height: (monitor.rect.height * monitor.output_info.scale_factor) as u32,
-                present_mode: wgpu::PresentMode::Mailbox,
-                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
-                view_formats: vec![cap.formats[0]],
-                desired_maximum_frame_latency: 2,
-            },
-        );
-
-        let mon_rendering = MonSpecificRendering::new(
-            &monitor.rect,
-            &monitor.output_info,
-            cap.formats[0],
-            monitor.image.to_rgba8(),
-            self,
-        );
-
-        // Reborrow mutably to set the renderer
-        let monitor = self
-            .monitors
-            .iter_mut()
-            .find(|window| window.layer == *layer)
-            .unwrap();
-
-        monitor.rendering = Some(mon_rendering);
-
-        log::info!("{:?}", cap.formats);
-
-        self.draw(MonitorIdentification::Layer(layer.clone()), qh);
+        self.configure_monitor(layer, qh);
     }
 }
@@ -4,7 +4,7 @@ use smithay_client_toolkit::{
     reexports::client::{Connection, QueueHandle, protocol::wl_output},
 };
 
-use crate::runtime_data::RuntimeData;
+use crate::{runtime_data::RuntimeData, types::ExitState};
 
 delegate_output!(RuntimeData);
 
@@ -15,25 +15,35 @@ impl OutputHandler for RuntimeData {
 
     fn new_output(
         &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
     ) {
+        if self.overlay_shown {
+            self.add_monitor(output, conn, qh);
+        }
     }
 
     fn update_output(
         &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
     ) {
+        self.update_monitor(&output, conn, qh);
     }
 
     fn output_destroyed(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        self.remove_monitor(&output);
+
+        if self.overlay_shown && self.monitors.is_empty() {
+            log::error!("All outputs are gone");
+            self.exit = ExitState::ExitOnly;
+        }
     }
 }
@@ -6,9 +6,14 @@ use smithay_client_toolkit::{
 };
 
 use crate::{
+    annotation::AnnotationTool,
     runtime_data::RuntimeData,
-    traits::ToGlobal,
-    types::{DisplaySelection, RectangleSelection, Selection, SelectionModifier, SelectionState},
+    snapping::SnapGuides,
+    traits::{Contains, ToGlobal},
+    types::{
+        AspectRatio, DisplaySelection, RectangleSelection, Selection, SelectionModifier,
+        SelectionState,
+    },
     window::FindWindowExt,
 };
 
@@ -24,23 +29,82 @@ impl PointerHandler for RuntimeData {
     ) {
         use PointerEventKind::*;
         for event in events {
-            let layer = self
+            // Events may still arrive for the surface of a removed output
+            let Some(layer) = self
                 .monitors
                 .iter()
                 .find(|layer| layer.wl_surface == event.surface)
-                .unwrap();
+            else {
+                continue;
+            };
             let global_pos = event.position.to_global(&layer.rect);
 
             match event.kind {
                 Enter { .. } => {
                     info!("Pointer entered @{:?}", event.position);
+
+                    self.pointer_position = Some(global_pos);
+
+                    if let Some(size) = self.fixed_size {
+                        self.place_fixed_size_selection(size, global_pos);
+                    }
+
+                    if self.select_window_on_enter {
+                        self.select_window_on_enter = false;
+                        self.selection = Selection::from_window(
+                            self.windows.find_by_position(&global_pos).cloned(),
+                        );
+                    }
                 }
                 Leave { .. } => {
                     info!("Pointer left");
                 }
                 Motion { .. } => {
+                    self.pointer_position = Some(global_pos);
+
+                    if let Some(size) = self.fixed_size {
+                        self.place_fixed_size_selection(size, global_pos);
+                        continue;
+                    }
+
+                    if self.annotations.is_dragging() {
+                        if let Some(rect) = self.selection_rect() {
+                            // Keep the annotation inside the selection
+                            self.annotations.drag(
+                                global_pos.0.clamp(rect.x, rect.x + rect.width),
+                                global_pos.1.clamp(rect.y, rect.y + rect.height),
+                            );
+                        }
+                        continue;
+                    }
+
+                    // Ctrl disables snapping while held
+                    let snap_edges = (self.config.snap_threshold > 0 && !self.modifiers.ctrl)
+                        .then(|| self.snap_edges());
+                    let threshold = self.config.snap_threshold;
+
+                    // Shift forces a square
+                    let ratio = if self.modifiers.shift {
+                        Some(AspectRatio::SQUARE)
+                    } else {
+                        self.aspect_ratio
+                    };
+
                     if let Selection::Rectangle(Some(selection)) = &mut self.selection {
                         if selection.active {
+                            // Moving snaps the whole selection, everything else the dragged point
+                            let (global_pos, mut guides) = match &snap_edges {
+                                Some(edges)
+                                    if !matches!(
+                                        selection.modifier,
+                                        Some(SelectionModifier::Center(..))
+                                    ) =>
+                                {
+                                    edges.snap_point(global_pos, threshold)
+                                }
+                                _ => (global_pos, SnapGuides::default()),
+                            };
+
                             match selection.modifier {
                                 // Handle selection modifiers, AKA the drag handles and moving it from the center
                                 Some(modifier) => match modifier {
@@ -78,6 +142,10 @@ impl PointerHandler for RuntimeData {
                                         extents.end_x -= x - global_pos.0;
                                         extents.end_y -= y - global_pos.1;
 
+                                        if let Some(edges) = &snap_edges {
+                                            guides = edges.snap_extents(&mut extents, threshold);
+                                        }
+
                                         selection.extents =
                                             extents.to_rect_clamped(&self.area).to_extents();
                                     }
@@ -87,12 +155,43 @@ impl PointerHandler for RuntimeData {
                                     selection.extents.end_y = global_pos.1;
                                 }
                             }
+
+                            if let Some(ratio) = ratio {
+                                selection.keep_ratio(ratio);
+                            }
+
+                            guides.retain_on(&selection.extents);
+                            self.snap_guides = guides;
                         }
                     }
                 }
                 Press { button, .. } => {
                     info!("Press {:x} @ {:?}", button, event.position);
 
+                    // Typing into a text is its own undo step, which ends when clicking elsewhere
+                    self.stop_editing_text();
+                    self.begin_edit();
+
+                    // Clicking places the selection following the pointer
+                    if self.fixed_size.take().is_some() {
+                        continue;
+                    }
+
+                    // Presses inside the selection draw with the annotation tool if one is selected
+                    if let (Some(tool), Some(rect)) = (self.annotations.tool, self.selection_rect())
+                    {
+                        if rect.contains(&global_pos) {
+                            self.annotations.press(
+                                tool,
+                                global_pos.0,
+                                global_pos.1,
+                                &self.config,
+                                &self.font,
+                            );
+                            continue;
+                        }
+                    }
+
                     match &mut self.selection {
                         Selection::Rectangle(selection) => {
                             let handles_state = RuntimeData::process_selection_handles(
@@ -136,9 +235,17 @@ impl PointerHandler for RuntimeData {
                 Release { button, .. } => {
                     info!("Release {:x} @ {:?}", button, event.position);
 
+                    self.annotations.finish();
+
                     if let Selection::Rectangle(Some(selection)) = &mut self.selection {
                         selection.active = false;
                     }
+                    self.snap_guides = SnapGuides::default();
+
+                    // The text placed or picked up is typed into as part of the same edit
+                    if self.annotations.editing.is_none() {
+                        self.end_edit();
+                    }
                 }
                 Axis {
                     horizontal,
@@ -146,6 +253,22 @@ impl PointerHandler for RuntimeData {
                     ..
                 } => {
                     info!("Scroll H:{:?}, V:{:?}", horizontal, vertical);
+
+                    // Scrolling up grows the text, scrolling down shrinks it
+                    if self.annotations.tool == Some(AnnotationTool::Text)
+                        && vertical.absolute != 0.0
+                    {
+                        self.begin_edit();
+                        self.annotations.resize_text(
+                            global_pos.0,
+                            global_pos.1,
+                            -vertical.absolute.signum() as f32,
+                            &self.font,
+                        );
+                        if self.annotations.editing.is_none() {
+                            self.end_edit();
+                        }
+                    }
                 }
             }
         }
@@ -0,0 +1,125 @@
+use crate::types::{Extents, Rect};
+
+/// Window borders and monitor edges the selection snaps to, in global coordinates
+pub struct SnapEdges {
+    x: Vec<i32>,
+    y: Vec<i32>,
+}
+
+/// The edges the selection is currently snapped to, drawn as guides
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnapGuides {
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+}
+
+impl SnapGuides {
+    /// Drop the guides of edges the selection got moved off of again,
+    /// for example to keep its aspect ratio
+    pub fn retain_on(&mut self, extents: &Extents) {
+        self.x = self
+            .x
+            .filter(|x| *x == extents.start_x || *x == extents.end_x);
+        self.y = self
+            .y
+            .filter(|y| *y == extents.start_y || *y == extents.end_y);
+    }
+}
+
+impl SnapEdges {
+    pub fn new<'a>(rects: impl IntoIterator<Item = &'a Rect<i32>>) -> Self {
+        let mut x = Vec::new();
+        let mut y = Vec::new();
+
+        for rect in rects {
+            x.extend([rect.x, rect.x + rect.width]);
+            y.extend([rect.y, rect.y + rect.height]);
+        }
+
+        Self { x, y }
+    }
+
+    /// Snap a dragged point to the closest edges within the threshold
+    pub fn snap_point(&self, (x, y): (i32, i32), threshold: i32) -> ((i32, i32), SnapGuides) {
+        let guides = SnapGuides {
+            x: closest_edge(&self.x, &[x], threshold).map(|(edge, _)| edge),
+            y: closest_edge(&self.y, &[y], threshold).map(|(edge, _)| edge),
+        };
+
+        ((guides.x.unwrap_or(x), guides.y.unwrap_or(y)), guides)
+    }
+
+    /// Shift the moved selection so its side closest to an edge within the threshold lies on it
+    pub fn snap_extents(&self, extents: &mut Extents, threshold: i32) -> SnapGuides {
+        let x = closest_edge(&self.x, &[extents.start_x, extents.end_x], threshold);
+        let y = closest_edge(&self.y, &[extents.start_y, extents.end_y], threshold);
+
+        if let Some((_, offset)) = x {
+            extents.start_x += offset;
+            extents.end_x += offset;
+        }
+
+        if let Some((_, offset)) = y {
+            extents.start_y += offset;
+            extents.end_y += offset;
+        }
+
+        SnapGuides {
+            x: x.map(|(edge, _)| edge),
+            y: y.map(|(edge, _)| edge),
+        }
+    }
+}
+
+/// Returns the edge closest to any of the values and the offset to move onto it
+fn closest_edge(edges: &[i32], values: &[i32], threshold: i32) -> Option<(i32, i32)> {
+    values
+        .iter()
+        .flat_map(|value| edges.iter().map(move |edge| (*edge, edge - value)))
+        .filter(|(_, offset)| offset.abs() <= threshold)
+        .min_by_key(|(_, offset)| offset.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snap() {
+        let edges = SnapEdges::new(&[Rect::new(0, 0, 1920, 1080), Rect::new(100, 200, 300, 400)]);
+
+        // Only edges within the threshold are snapped to
+        assert_eq!(
+            edges.snap_point((395, 50), 8),
+            (
+                (400, 50),
+                SnapGuides {
+                    x: Some(400),
+                    y: None
+                }
+            )
+        );
+        assert_eq!(edges.snap_point((380, 50), 8).1, SnapGuides::default());
+
+        // The side closer to an edge wins
+        let mut extents = Extents {
+            start_x: 95,
+            start_y: 500,
+            end_x: 403,
+            end_y: 603,
+        };
+        let guides = edges.snap_extents(&mut extents, 8);
+
+        assert_eq!(guides.x, Some(400));
+        assert_eq!(guides.y, Some(600));
+        assert_eq!(
+            extents,
+            Extents {
+                start_x: 92,
+                start_y: 497,
+                end_x: 400,
+                end_y: 600,
+            }
+        );
+    }
+}
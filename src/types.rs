@@ -1,11 +1,15 @@
-use std::{env, fs};
+use std::{env, fs, str::FromStr};
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, error::ErrorKind};
 use image::DynamicImage;
-use libwayshot::WayshotConnection;
 use serde::Deserialize;
 use smithay_client_toolkit::{
     output::OutputInfo,
+    reexports::protocols::wp::{
+        fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
+        viewporter::client::wp_viewport::WpViewport,
+    },
+    seat::keyboard::Modifiers,
     shell::{
         WaylandSurface,
         wlr_layer::{Anchor, KeyboardInteractivity, Layer, LayerSurface},
@@ -21,6 +25,9 @@ use wgpu::rwh::{
 };
 
 use crate::{
+    encoding::{Format, PngCompression, PngFilter},
+    filename::DEFAULT_FILENAME_TEMPLATE,
+    geometry::{DEFAULT_GEOMETRY_FORMAT, parse_geometry},
     rendering::MonSpecificRendering,
     runtime_data::RuntimeData,
     window::{WindowDescriptor, search::WindowSearchParam},
@@ -33,10 +40,15 @@ pub struct Args {
     #[arg(short, long)]
     pub copy: bool,
 
-    /// Output the screenshot into stdout in PNG format
+    /// Output the screenshot into stdout
     #[arg(short, long)]
     pub stdout: bool,
 
+    /// The format to copy, print and save into a directory in, like `format` in the config.
+    /// Saving to a path uses the format of its extension.
+    #[arg(long, value_enum)]
+    pub format: Option<Format>,
+
     /// Save the image into a file
     #[command(subcommand)]
     pub save: Option<SaveLocation>,
@@ -47,7 +59,9 @@ pub struct Args {
     #[arg(long, group = "capture-window")]
     pub window_search: Option<WindowSearchParam>,
 
-    /// Pre-selects the window under the mouse cursor.
+    /// Pre-selects the window under the mouse cursor. On compositors that don't report
+    /// the cursor position (sway, niri), the window is picked when the pointer enters
+    /// the overlay, or with --auto-capture the focused window is taken instead.
     #[arg(long, group = "capture-window")]
     pub window_under_cursor: bool,
 
@@ -58,18 +72,87 @@ pub struct Args {
     /// Automatically captures the pre-selected window, skipping interactive mode.
     #[arg(long)]
     pub auto_capture: bool,
+
+    /// Captures the area in global logical coordinates, skipping interactive mode.
+    /// Takes the format slurp prints by default, e.g. "10,20 300x200".
+    #[arg(long, group = "capture-direct", value_parser = parse_geometry)]
+    pub geometry: Option<Rect<i32>>,
+
+    /// Captures the output with the name, e.g. "DP-1", skipping interactive mode.
+    #[arg(long, group = "capture-direct")]
+    pub output: Option<String>,
+
+    /// Captures the whole desktop spanning all outputs, skipping interactive mode.
+    #[arg(long, group = "capture-direct")]
+    pub desktop: bool,
+
+    /// Keeps the selection at a width to height ratio, e.g. "16:9".
+    /// Overrides the ratio from the config.
+    #[arg(long)]
+    pub aspect_ratio: Option<AspectRatio>,
+
+    /// Spawns a selection with a fixed size in logical pixels that follows
+    /// the cursor until clicked, e.g. "1280x720".
+    #[arg(long)]
+    pub size: Option<Size>,
+
+    /// Includes the cursor in the screenshot, like `cursor: true` in the config.
+    #[arg(long)]
+    pub cursor: bool,
+
+    /// Waits the amount of seconds before taking the screenshot, e.g. to capture
+    /// menus and tooltips. Counts down on top of a pre-selected window.
+    #[arg(long, value_name = "SECONDS")]
+    pub delay: Option<f32>,
+
+    /// Prints the geometry of the selection instead of capturing it, like slurp.
+    /// Supports %x, %y, %w, %h, %X and %Y (relative to the output), %o (output name) and %%.
+    /// Exits with a non-zero code if cancelled.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_GEOMETRY_FORMAT,
+        conflicts_with_all = ["copy", "stdout"]
+    )]
+    pub print_geometry: Option<String>,
+}
+
+impl Args {
+    /// Parse the command line, exiting with the usage on conflicts clap can't
+    /// express, like an argument with a subcommand
+    pub fn parse_checked() -> Self {
+        let args = Self::parse();
+
+        if let Err(error) = args.check_conflicts() {
+            error.exit();
+        }
+        args
+    }
+
+    fn check_conflicts(&self) -> Result<(), clap::Error> {
+        if self.print_geometry.is_some() && self.save.is_some() {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--print-geometry can't be used with saving, nothing is captured",
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Subcommand, Clone, Debug)]
 pub enum SaveLocation {
     /// The path to save the image to
     Path { path: String },
-    /// The directory to save the image to with a generated name
+    /// The directory to save the image to with a name from `filename_template` in the config
     Directory { path: String },
 }
 
 /// The configuration for colors and other things like that
 #[derive(Debug, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub handle_radius: i32,
     pub line_width: i32,
@@ -79,6 +162,47 @@ pub struct Config {
     pub text_color: Color,
     pub mode_text_size: i32,
     pub font_family: String,
+    pub annotation_color: Color,
+    pub annotation_line_width: i32,
+    /// Multiplied with the image below, the alpha controls the strength
+    pub highlighter_color: Color,
+    pub highlighter_width: i32,
+    /// Initial line height of text annotations, in logical pixels
+    pub annotation_text_size: i32,
+    /// Size of the blocks of the pixelate tool, in logical pixels
+    pub pixelate_size: i32,
+    /// Standard deviation of the blur tool, in logical pixels
+    pub blur_sigma: i32,
+    /// Show a zoomed in view next to the pointer while dragging the selection
+    pub magnifier: bool,
+    /// Width of the magnifier in logical pixels
+    pub magnifier_size: i32,
+    /// Size each pixel of the screenshot is drawn with in the magnifier
+    pub magnifier_zoom: i32,
+    /// Keep the selection at this ratio, e.g. `Some("16:9")`
+    pub aspect_ratio: Option<AspectRatio>,
+    /// Distance in logical pixels within which the selection snaps to window
+    /// borders and monitor edges, 0 turns snapping off
+    pub snap_threshold: i32,
+    /// Show the remaining seconds of `--delay` on top of a pre-selected window
+    pub countdown: bool,
+    /// Include the cursor in the screenshot
+    pub cursor: bool,
+    /// Scale of selections spanning outputs with different scales
+    pub crop_scale: CropScale,
+    /// The format to copy, print and save into a directory in
+    pub format: Format,
+    /// Quality of JPEG images from 1 to 100
+    pub jpeg_quality: u8,
+    /// Quality of AVIF images from 1 to 100
+    pub avif_quality: u8,
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    /// Name of screenshots saved into a directory, without the extension
+    pub filename_template: String,
+    /// Leave out where the screenshot came from when saving it. AVIF and PPM
+    /// files never contain it.
+    pub strip_metadata: bool,
 }
 
 impl Config {
@@ -114,10 +238,154 @@ impl Default for Config {
             },
             mode_text_size: 30,
             font_family: "monospace".to_string(),
+            annotation_color: Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            },
+            annotation_line_width: 3,
+            highlighter_color: Color {
+                r: 1.0,
+                g: 0.9,
+                b: 0.0,
+                a: 1.0,
+            },
+            highlighter_width: 16,
+            annotation_text_size: 24,
+            pixelate_size: 12,
+            blur_sigma: 8,
+            magnifier: true,
+            magnifier_size: 160,
+            magnifier_zoom: 10,
+            aspect_ratio: None,
+            snap_threshold: 8,
+            countdown: true,
+            cursor: false,
+            crop_scale: CropScale::Max,
+            format: Format::Png,
+            jpeg_quality: 90,
+            avif_quality: 80,
+            png_compression: PngCompression::Fast,
+            png_filter: PngFilter::Adaptive,
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            strip_metadata: false,
         }
     }
 }
 
+/// Pixels per logical pixel of a selection spanning outputs with different scales
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum CropScale {
+    /// The highest scale among the outputs, upscaling the other parts
+    Max,
+    /// The lowest scale among the outputs, downscaling the other parts
+    Min,
+    /// A fixed scale, resampling every part
+    Fixed(f32),
+}
+
+/// Width to height ratio of the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    pub const SQUARE: Self = Self {
+        width: 1,
+        height: 1,
+    };
+
+    fn ratio(self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    /// Move the corner so the rectangle spanning from the anchor to it has the
+    /// ratio, growing the shorter side
+    pub fn fit(self, anchor: (i32, i32), corner: (i32, i32)) -> (i32, i32) {
+        let (dx, dy) = ((corner.0 - anchor.0) as f32, (corner.1 - anchor.1) as f32);
+        let (sign_x, sign_y) = (
+            if dx < 0.0 { -1.0 } else { 1.0 },
+            if dy < 0.0 { -1.0 } else { 1.0 },
+        );
+
+        let (width, height) = if dx.abs() / self.ratio() >= dy.abs() {
+            (dx.abs(), dx.abs() / self.ratio())
+        } else {
+            (dy.abs() * self.ratio(), dy.abs())
+        };
+
+        (
+            anchor.0 + (sign_x * width).round() as i32,
+            anchor.1 + (sign_y * height).round() as i32,
+        )
+    }
+}
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid aspect ratio \"{}\", it should be in the form of \"width:height\"",
+                s
+            )
+        };
+
+        let (width, height) = s.split_once(':').ok_or_else(invalid)?;
+        let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+        let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self { width, height })
+    }
+}
+
+impl TryFrom<String> for AspectRatio {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Size of the selection in logical pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid size \"{}\", it should be in the form of \"widthxheight\"",
+                s
+            )
+        };
+
+        let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+        let width: i32 = width.trim().parse().map_err(|_| invalid())?;
+        let height: i32 = height.trim().parse().map_err(|_| invalid())?;
+
+        if width <= 0 || height <= 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self { width, height })
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Deserialize, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Color {
@@ -134,18 +402,34 @@ impl From<Color> for wgpu_text::glyph_brush::Color {
 }
 
 /// Represents the layer and the monitor it resides on
+/// The overlay on a single output. The fields drop in order, so the wgpu resources
+/// go before the wayland surface they render to.
 pub struct Monitor {
+    pub rendering: Option<MonSpecificRendering>,
+    pub surface: wgpu::Surface<'static>,
+    pub output: wl_output::WlOutput,
     pub layer: LayerSurface,
     pub wl_surface: wl_surface::WlSurface,
-    pub surface: wgpu::Surface<'static>,
     pub output_info: OutputInfo,
     pub rect: Rect<i32>,
     pub image: DynamicImage,
-    /// The wayland scale factor for this monitor
-    pub rendering: Option<MonSpecificRendering>,
+    /// The wayland scale factor for this monitor, fractional if the compositor supports it
+    pub scale: f32,
+    /// Maps the buffer at the physical size onto the logical size with fractional scales
+    pub viewport: Option<WpViewport>,
+    pub fractional_scale: Option<WpFractionalScaleV1>,
 }
 
 impl Monitor {
+    /// Logical position and size of the output in global coordinates
+    pub fn logical_rect(info: &OutputInfo) -> Option<Rect<i32>> {
+        let (x, y) = info.logical_position?;
+        let (width, height) = info.logical_size?;
+
+        Some(Rect::new(x, y, width, height))
+    }
+
+    /// Show the overlay on the output, `None` if there is no screenshot of it
     pub fn new(
         rect: Rect<i32>,
         qh: &QueueHandle<RuntimeData>,
@@ -153,7 +437,14 @@ impl Monitor {
         output: wl_output::WlOutput,
         info: OutputInfo,
         runtime_data: &RuntimeData,
-    ) -> Self {
+    ) -> Option<Self> {
+        // Taken before any overlay was shown
+        let image = runtime_data
+            .capture
+            .output(info.name.as_deref(), &rect)?
+            .image
+            .clone();
+
         let wl_surface = runtime_data.compositor_state.create_surface(qh);
 
         let layer = runtime_data.layer_state.create_layer_surface(
@@ -164,8 +455,29 @@ impl Monitor {
             Some(&output),
         );
 
-        // Set the right scale for the buffer
-        wl_surface.set_buffer_scale(info.scale_factor);
+        // Fractional scales need the buffer mapped onto the logical size by a viewport,
+        // integer ones can use the buffer scale
+        let (viewport, fractional_scale) = match (
+            &runtime_data.viewporter,
+            &runtime_data.fractional_scale_manager,
+        ) {
+            (Some(viewporter), Some(fractional_scale_manager)) => {
+                let viewport = viewporter.get_viewport(&wl_surface, qh, ());
+                viewport.set_destination(rect.width, rect.height);
+
+                let fractional_scale = fractional_scale_manager.get_fractional_scale(
+                    &wl_surface,
+                    qh,
+                    wl_surface.clone(),
+                );
+
+                (Some(viewport), Some(fractional_scale))
+            }
+            _ => {
+                wl_surface.set_buffer_scale(info.scale_factor);
+                (None, None)
+            }
+        };
 
         layer.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
         layer.set_exclusive_zone(-1);
@@ -173,9 +485,6 @@ impl Monitor {
 
         layer.commit();
 
-        let wayshot_connection = WayshotConnection::new().unwrap();
-        let image = wayshot_connection.screenshot_all(false).unwrap();
-
         let handle = RawWgpuHandles::new(conn, &wl_surface);
 
         let surface = unsafe {
@@ -188,19 +497,36 @@ impl Monitor {
                 .unwrap()
         };
 
-        Self {
+        Some(Self {
+            output,
             layer,
             wl_surface,
             rect,
+            // Until the compositor reports the preferred fractional scale
+            scale: info.scale_factor as f32,
+            viewport,
+            fractional_scale,
             output_info: info,
             image,
             surface,
             rendering: None,
+        })
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        // Before the surface gets destroyed along with the layer
+        if let Some(viewport) = &self.viewport {
+            viewport.destroy();
+        }
+        if let Some(fractional_scale) = &self.fractional_scale {
+            fractional_scale.destroy();
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Extents {
     pub start_x: i32,
     pub start_y: i32,
@@ -259,6 +585,14 @@ impl<T> Rect<T> {
 }
 
 impl Rect<i32> {
+    /// Size in physical pixels at the scale
+    pub fn physical_size(&self, scale: f32) -> (u32, u32) {
+        (
+            (self.width as f32 * scale).round() as u32,
+            (self.height as f32 * scale).round() as u32,
+        )
+    }
+
     pub fn intersects(&self, other: &Self) -> bool {
         ((self.x + self.width).min(other.x + other.width) - self.x.max(other.x)) > 0
             && ((self.y + self.height).min(other.y + other.height) - self.y.max(other.y)) > 0
@@ -287,6 +621,20 @@ impl Rect<i32> {
         *self = Self::new(x, y, width, height);
     }
 
+    /// The overlapping part of the two rectangles
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        if !self.intersects(other) {
+            return None;
+        }
+
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let width = (self.x + self.width).min(other.x + other.width) - x;
+        let height = (self.y + self.height).min(other.y + other.height) - y;
+
+        Some(Self::new(x, y, width, height))
+    }
+
     /// Constrain the rectangle to fit inside the provided rectangle
     pub fn constrain(&self, area: &Self) -> Option<Self> {
         if !self.intersects(area) {
@@ -305,7 +653,7 @@ impl Rect<i32> {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SelectionModifier {
     Left,
     Right,
@@ -319,7 +667,7 @@ pub enum SelectionModifier {
     Center(i32, i32, Extents),
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum Selection {
     Rectangle(Option<RectangleSelection>),
     Display(Option<DisplaySelection>),
@@ -335,11 +683,13 @@ impl Default for Selection {
 impl Selection {
     pub fn flattened(&self) -> Selection {
         match self {
-            Self::Window(Some(window)) => Self::Rectangle(Some(RectangleSelection {
-                extents: window.rect.to_extents(),
-                modifier: None,
-                active: false,
-            })),
+            Self::Window(Some(window)) => {
+                Self::Rectangle(window.rect.map(|rect| RectangleSelection {
+                    extents: rect.to_extents(),
+                    modifier: None,
+                    active: false,
+                }))
+            }
             Self::Window(None) => Self::Rectangle(None),
             _ => self.clone(),
         }
@@ -347,20 +697,27 @@ impl Selection {
 
     pub fn from_window(window: Option<WindowDescriptor>) -> Self {
         match window {
-            Some(window) => Self::Window(Some(window)),
+            Some(window) if window.rect.is_some() => Self::Window(Some(window)),
+            Some(window) => {
+                log::warn!(
+                    "The window \"{}\" can't be selected, its geometry is unknown",
+                    window.title
+                );
+                Self::Rectangle(None)
+            }
             None => Self::Rectangle(None),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RectangleSelection {
     pub extents: Extents,
     pub modifier: Option<SelectionModifier>,
     pub active: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DisplaySelection {
     pub wl_surface: wl_surface::WlSurface,
 }
@@ -374,6 +731,63 @@ impl DisplaySelection {
 }
 
 impl RectangleSelection {
+    /// Adjust the side being dragged with the current modifier to keep the ratio
+    pub fn keep_ratio(&mut self, ratio: AspectRatio) {
+        let extents = &mut self.extents;
+
+        // The corner across from the one being dragged stays in place
+        match self.modifier {
+            None | Some(SelectionModifier::BottomRight) => {
+                (extents.end_x, extents.end_y) = ratio.fit(
+                    (extents.start_x, extents.start_y),
+                    (extents.end_x, extents.end_y),
+                );
+            }
+            Some(SelectionModifier::TopLeft) => {
+                (extents.start_x, extents.start_y) = ratio.fit(
+                    (extents.end_x, extents.end_y),
+                    (extents.start_x, extents.start_y),
+                );
+            }
+            Some(SelectionModifier::TopRight) => {
+                (extents.end_x, extents.start_y) = ratio.fit(
+                    (extents.start_x, extents.end_y),
+                    (extents.end_x, extents.start_y),
+                );
+            }
+            Some(SelectionModifier::BottomLeft) => {
+                (extents.start_x, extents.end_y) = ratio.fit(
+                    (extents.end_x, extents.start_y),
+                    (extents.start_x, extents.end_y),
+                );
+            }
+            // Edges drag the adjacent side along
+            Some(SelectionModifier::Left | SelectionModifier::Right) => {
+                let width = (extents.end_x - extents.start_x).abs() as f32;
+                let height = (width * ratio.height as f32 / ratio.width as f32).round() as i32;
+                let sign = if extents.end_y < extents.start_y {
+                    -1
+                } else {
+                    1
+                };
+
+                extents.end_y = extents.start_y + sign * height;
+            }
+            Some(SelectionModifier::Top | SelectionModifier::Bottom) => {
+                let height = (extents.end_y - extents.start_y).abs() as f32;
+                let width = (height * ratio.width as f32 / ratio.height as f32).round() as i32;
+                let sign = if extents.end_x < extents.start_x {
+                    -1
+                } else {
+                    1
+                };
+
+                extents.end_x = extents.start_x + sign * width;
+            }
+            Some(SelectionModifier::Center(..)) => (),
+        }
+    }
+
     pub fn new(x: i32, y: i32) -> Self {
         Self {
             extents: Extents {
@@ -407,6 +821,84 @@ pub struct RawWgpuHandles {
     display: RawDisplayHandle,
 }
 
+/// Amount of logical pixels the arrow keys move or resize the selection by
+const KEYBOARD_STEP: i32 = 1;
+/// Applied to the step while Alt is held
+const KEYBOARD_STEP_MULTIPLIER: i32 = 10;
+
+/// How the arrow keys change the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardEdit {
+    Move,
+    /// Push the edge on the side of the arrow outwards
+    Grow,
+    /// Pull the edge on the side of the arrow inwards
+    Shrink,
+}
+
+impl KeyboardEdit {
+    /// The edit of an arrow key and its step: Shift grows, Ctrl shrinks and Alt
+    /// multiplies the step
+    pub fn from_modifiers(modifiers: &Modifiers) -> (Self, i32) {
+        let edit = if modifiers.shift {
+            Self::Grow
+        } else if modifiers.ctrl {
+            Self::Shrink
+        } else {
+            Self::Move
+        };
+        let step = if modifiers.alt {
+            KEYBOARD_STEP * KEYBOARD_STEP_MULTIPLIER
+        } else {
+            KEYBOARD_STEP
+        };
+
+        (edit, step)
+    }
+
+    /// Move or resize the rectangle by `step` in the direction of an arrow key,
+    /// keeping it inside the area
+    pub fn apply(
+        self,
+        rect: Rect<i32>,
+        direction: (i32, i32),
+        step: i32,
+        area: &Rect<i32>,
+    ) -> Rect<i32> {
+        let (mut left, mut top) = (rect.x, rect.y);
+        let (mut right, mut bottom) = (rect.x + rect.width, rect.y + rect.height);
+        let (area_right, area_bottom) = (area.x + area.width, area.y + area.height);
+
+        match (self, direction) {
+            (Self::Move, (x, y)) => {
+                left += x * step;
+                right += x * step;
+                top += y * step;
+                bottom += y * step;
+            }
+            (Self::Grow, (1, _)) => right = (right + step).min(area_right),
+            (Self::Grow, (-1, _)) => left = (left - step).max(area.x),
+            (Self::Grow, (_, 1)) => bottom = (bottom + step).min(area_bottom),
+            (Self::Grow, (_, -1)) => top = (top - step).max(area.y),
+            // Never down to nothing, which couldn't be captured
+            (Self::Shrink, (1, _)) => right = (right - step).max(left + 1),
+            (Self::Shrink, (-1, _)) => left = (left + step).min(right - 1),
+            (Self::Shrink, (_, 1)) => bottom = (bottom - step).max(top + 1),
+            (Self::Shrink, (_, -1)) => top = (top + step).min(bottom - 1),
+            _ => return rect,
+        }
+
+        Extents {
+            start_x: left,
+            start_y: top,
+            // Never larger than the area, so it can be clamped into it
+            end_x: right.min(left + area.width),
+            end_y: bottom.min(top + area.height),
+        }
+        .to_rect_clamped(area)
+    }
+}
+
 pub enum SelectionState {
     CenterChanged,
     HandlesChanged,
@@ -443,3 +935,144 @@ impl HasDisplayHandle for RawWgpuHandles {
         Ok(handle)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+
+        // Printing the geometry doesn't capture anything
+        for conflicting in [
+            &["watershot", "--print-geometry", "--copy"][..],
+            &["watershot", "--print-geometry", "--stdout"],
+        ] {
+            assert!(Args::try_parse_from(conflicting).is_err());
+        }
+
+        let args =
+            Args::try_parse_from(["watershot", "--print-geometry", "%x", "path", "shot.png"]);
+        assert!(args.unwrap().check_conflicts().is_err());
+        let args = Args::try_parse_from(["watershot", "--print-geometry"]);
+        assert!(args.unwrap().check_conflicts().is_ok());
+    }
+
+    #[test]
+    fn test_aspect_ratio_from_str() {
+        assert_eq!(
+            "16:9".parse(),
+            Ok(AspectRatio {
+                width: 16,
+                height: 9
+            })
+        );
+        assert!("16".parse::<AspectRatio>().is_err());
+        assert!("0:9".parse::<AspectRatio>().is_err());
+        assert!("16x9".parse::<AspectRatio>().is_err());
+
+        assert_eq!(
+            "1280x720".parse(),
+            Ok(Size {
+                width: 1280,
+                height: 720
+            })
+        );
+        assert!("1280:720".parse::<Size>().is_err());
+    }
+
+    #[test]
+    fn test_aspect_ratio_fit() {
+        let ratio = AspectRatio {
+            width: 16,
+            height: 9,
+        };
+
+        // The shorter side grows to match the longer one
+        assert_eq!(ratio.fit((0, 0), (160, 10)), (160, 90));
+        assert_eq!(ratio.fit((0, 0), (10, 90)), (160, 90));
+        // Dragging towards the top left of the anchor
+        assert_eq!(ratio.fit((100, 100), (-60, 90)), (-60, 10));
+        assert_eq!(AspectRatio::SQUARE.fit((0, 0), (30, -50)), (50, -50));
+    }
+
+    #[test]
+    fn test_keyboard_edit_from_modifiers() {
+        let modifiers = |shift, ctrl, alt| Modifiers {
+            shift,
+            ctrl,
+            alt,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            KeyboardEdit::from_modifiers(&modifiers(false, false, false)),
+            (KeyboardEdit::Move, 1)
+        );
+        assert_eq!(
+            KeyboardEdit::from_modifiers(&modifiers(true, false, false)),
+            (KeyboardEdit::Grow, 1)
+        );
+        assert_eq!(
+            KeyboardEdit::from_modifiers(&modifiers(false, true, true)),
+            (KeyboardEdit::Shrink, 10)
+        );
+        // Shift wins over Ctrl
+        assert_eq!(
+            KeyboardEdit::from_modifiers(&modifiers(true, true, false)),
+            (KeyboardEdit::Grow, 1)
+        );
+    }
+
+    #[test]
+    fn test_keyboard_edit_apply() {
+        let area = Rect::new(-100, 0, 300, 200);
+        let rect = Rect::new(0, 10, 50, 40);
+
+        assert_eq!(
+            KeyboardEdit::Move.apply(rect, (1, 0), 10, &area),
+            Rect::new(10, 10, 50, 40)
+        );
+        assert_eq!(
+            KeyboardEdit::Grow.apply(rect, (-1, 0), 10, &area),
+            Rect::new(-10, 10, 60, 40)
+        );
+        assert_eq!(
+            KeyboardEdit::Grow.apply(rect, (0, 1), 1, &area),
+            Rect::new(0, 10, 50, 41)
+        );
+        assert_eq!(
+            KeyboardEdit::Shrink.apply(rect, (0, -1), 10, &area),
+            Rect::new(0, 20, 50, 30)
+        );
+
+        // Moving stops at the edges of the area
+        assert_eq!(
+            KeyboardEdit::Move.apply(rect, (0, -1), 20, &area),
+            Rect::new(0, 0, 50, 40)
+        );
+        // Growing too
+        assert_eq!(
+            KeyboardEdit::Grow.apply(rect, (1, 0), 500, &area),
+            Rect::new(0, 10, 200, 40)
+        );
+        // Shrinking keeps a pixel
+        assert_eq!(
+            KeyboardEdit::Shrink.apply(rect, (1, 0), 100, &area),
+            Rect::new(0, 10, 1, 40)
+        );
+    }
+
+    #[test]
+    fn test_physical_size() {
+        assert_eq!(Rect::new(0, 0, 1920, 1080).physical_size(1.0), (1920, 1080));
+        assert_eq!(
+            Rect::new(10, 20, 1536, 864).physical_size(1.25),
+            (1920, 1080)
+        );
+        // Odd logical sizes round to the nearest physical pixel
+        assert_eq!(Rect::new(0, 0, 1366, 767).physical_size(1.5), (2049, 1151));
+        assert_eq!(Rect::new(0, 0, 3, 5).physical_size(1.25), (4, 6));
+    }
+}
@@ -0,0 +1,419 @@
+use std::cell::RefCell;
+
+use log::{info, warn};
+use smithay_client_toolkit::{
+    delegate_output,
+    output::{OutputHandler, OutputState},
+    reexports::{
+        client::{
+            Connection, Dispatch, EventQueue, Proxy, QueueHandle, backend::ObjectId,
+            event_created_child, globals::GlobalList, protocol::wl_output,
+        },
+        protocols::ext::foreign_toplevel_list::v1::client::{
+            ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+            ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+        },
+        protocols_wlr::foreign_toplevel::v1::client::{
+            zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+            zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+        },
+    },
+};
+
+use crate::types::{Monitor, Rect};
+
+use super::{CompositorBackend, CompositorNotAvailable, WindowDescriptor};
+
+/// Compositor-agnostic backend using the foreign toplevel protocols.
+///
+/// Both protocols report titles and app ids, but neither reports where a
+/// toplevel is. The wlr protocol also reports the outputs a toplevel is on and
+/// whether it's fullscreen, which puts fullscreen toplevels exactly on their
+/// output. Every other toplevel is listed without a geometry and can't be selected.
+pub struct ForeignToplevelBackend {
+    /// The toplevels keep being tracked on their own queue of the connection, and
+    /// are updated whenever the windows are requested
+    queue: RefCell<(EventQueue<ToplevelState>, ToplevelState)>,
+}
+
+impl CompositorBackend for ForeignToplevelBackend {
+    fn get_all_windows(&self) -> Vec<WindowDescriptor> {
+        self.windows().0
+    }
+
+    fn get_focused(&self) -> Option<WindowDescriptor> {
+        self.windows().1
+    }
+
+    fn get_mouse_position(&self) -> Option<(i32, i32)> {
+        None
+    }
+}
+
+impl ForeignToplevelBackend {
+    /// Unlike the IPC backends, this one uses the Wayland connection of the application
+    pub fn try_new(
+        conn: &Connection,
+        globals: &GlobalList,
+    ) -> Result<Box<dyn CompositorBackend>, CompositorNotAvailable> {
+        let mut event_queue = conn.new_event_queue();
+        let qh = event_queue.handle();
+
+        let mut state = ToplevelState {
+            output_state: OutputState::new(globals, &qh),
+            toplevels: Vec::new(),
+        };
+
+        // The wlr protocol is preferred as it also reports outputs and states
+        let manager = globals
+            .bind::<ZwlrForeignToplevelManagerV1, _, _>(&qh, 1..=3, ())
+            .ok();
+        let list = match manager {
+            Some(_) => None,
+            None => globals
+                .bind::<ExtForeignToplevelListV1, _, _>(&qh, 1..=1, ())
+                .ok(),
+        };
+
+        if manager.is_none() && list.is_none() {
+            return Err(CompositorNotAvailable::NotInstalled);
+        }
+
+        // The first roundtrip announces the outputs and toplevels, the second one their properties
+        for _ in 0..2 {
+            event_queue
+                .roundtrip(&mut state)
+                .map_err(|_| CompositorNotAvailable::NotRunning)?;
+        }
+
+        if !state.windows().0.iter().any(|window| window.rect.is_some()) {
+            info!(
+                "No toplevel has a known geometry, only fullscreen ones can be selected in window mode"
+            );
+        }
+
+        Ok(Box::new(ForeignToplevelBackend {
+            queue: RefCell::new((event_queue, state)),
+        }))
+    }
+
+    /// Read the changes since the last call, then list the toplevels and the focused one
+    fn windows(&self) -> (Vec<WindowDescriptor>, Option<WindowDescriptor>) {
+        let (event_queue, state) = &mut *self.queue.borrow_mut();
+
+        if let Err(err) = event_queue.roundtrip(state) {
+            warn!(
+                "Failed to update the toplevels, they may be outdated: {}",
+                err
+            );
+        }
+
+        state.windows()
+    }
+}
+
+/// A property of a toplevel, the same for both protocols
+enum ToplevelEvent<O> {
+    Title(String),
+    AppId(String),
+    OutputEnter(O),
+    OutputLeave(O),
+    /// The raw array of `zwlr_foreign_toplevel_handle_v1::State` values
+    State(Vec<u8>),
+}
+
+/// Generic over the output so the event handling can be tested without a compositor
+struct Toplevel<O> {
+    title: String,
+    app_id: String,
+    outputs: Vec<O>,
+    activated: bool,
+    minimized: bool,
+    fullscreen: bool,
+}
+
+impl<O: PartialEq> Toplevel<O> {
+    fn new() -> Self {
+        Self {
+            title: String::new(),
+            app_id: String::new(),
+            outputs: Vec::new(),
+            activated: false,
+            minimized: false,
+            fullscreen: false,
+        }
+    }
+
+    fn apply(&mut self, event: ToplevelEvent<O>) {
+        use zwlr_foreign_toplevel_handle_v1::State;
+
+        match event {
+            ToplevelEvent::Title(title) => self.title = title,
+            ToplevelEvent::AppId(app_id) => self.app_id = app_id,
+            ToplevelEvent::OutputEnter(output) => {
+                if !self.outputs.contains(&output) {
+                    self.outputs.push(output);
+                }
+            }
+            ToplevelEvent::OutputLeave(output) => self.outputs.retain(|o| *o != output),
+            ToplevelEvent::State(raw) => {
+                let states: Vec<u32> = raw
+                    .chunks_exact(4)
+                    .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+                    .collect();
+
+                self.activated = states.contains(&(State::Activated as u32));
+                self.minimized = states.contains(&(State::Minimized as u32));
+                self.fullscreen = states.contains(&(State::Fullscreen as u32));
+            }
+        }
+    }
+
+    /// Only a fullscreen toplevel has a known geometry, the one of its output. The
+    /// outputs bound elsewhere on the connection are also entered, but have no rect.
+    fn rect(&self, output_rect: impl Fn(&O) -> Option<Rect<i32>>) -> Option<Rect<i32>> {
+        if !self.fullscreen || self.minimized {
+            return None;
+        }
+
+        self.outputs.iter().find_map(output_rect)
+    }
+
+    fn window(&self, output_rect: impl Fn(&O) -> Option<Rect<i32>>) -> WindowDescriptor {
+        WindowDescriptor {
+            initial_title: self.title.clone(),
+            title: self.title.clone(),
+            initial_class: self.app_id.clone(),
+            class: self.app_id.clone(),
+            rect: self.rect(output_rect),
+        }
+    }
+}
+
+struct ToplevelState {
+    output_state: OutputState,
+    toplevels: Vec<(ObjectId, Toplevel<wl_output::WlOutput>)>,
+}
+
+impl ToplevelState {
+    /// The active toplevel is listed first, as it's the most likely one to be on top
+    fn windows(&self) -> (Vec<WindowDescriptor>, Option<WindowDescriptor>) {
+        let mut windows = Vec::new();
+        let mut focused = None;
+
+        for (_, toplevel) in &self.toplevels {
+            let window =
+                toplevel.window(|output| Monitor::logical_rect(&self.output_state.info(output)?));
+
+            if toplevel.activated {
+                focused = Some(window.clone());
+                windows.insert(0, window);
+            } else {
+                windows.push(window);
+            }
+        }
+
+        (windows, focused)
+    }
+
+    fn apply(&mut self, handle: &impl Proxy, event: ToplevelEvent<wl_output::WlOutput>) {
+        if let Some((_, toplevel)) = self.toplevels.iter_mut().find(|(id, _)| *id == handle.id()) {
+            toplevel.apply(event);
+        }
+    }
+
+    fn remove_toplevel(&mut self, handle: &impl Proxy) {
+        self.toplevels.retain(|(id, _)| *id != handle.id());
+    }
+}
+
+delegate_output!(ToplevelState);
+
+impl OutputHandler for ToplevelState {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for ToplevelState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.push((toplevel.id(), Toplevel::new()));
+        }
+    }
+
+    event_created_child!(ToplevelState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for ToplevelState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use zwlr_foreign_toplevel_handle_v1::Event;
+
+        let event = match event {
+            Event::Title { title } => ToplevelEvent::Title(title),
+            Event::AppId { app_id } => ToplevelEvent::AppId(app_id),
+            Event::OutputEnter { output } => ToplevelEvent::OutputEnter(output),
+            Event::OutputLeave { output } => ToplevelEvent::OutputLeave(output),
+            Event::State { state } => ToplevelEvent::State(state),
+            Event::Closed => {
+                state.remove_toplevel(handle);
+                handle.destroy();
+                return;
+            }
+            _ => return,
+        };
+
+        state.apply(handle, event);
+    }
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ()> for ToplevelState {
+    fn event(
+        state: &mut Self,
+        _: &ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.push((toplevel.id(), Toplevel::new()));
+        }
+    }
+
+    event_created_child!(ToplevelState, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for ToplevelState {
+    fn event(
+        state: &mut Self,
+        handle: &ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use ext_foreign_toplevel_handle_v1::Event;
+
+        let event = match event {
+            Event::Title { title } => ToplevelEvent::Title(title),
+            Event::AppId { app_id } => ToplevelEvent::AppId(app_id),
+            Event::Closed => {
+                state.remove_toplevel(handle);
+                handle.destroy();
+                return;
+            }
+            _ => return,
+        };
+
+        state.apply(handle, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zwlr_foreign_toplevel_handle_v1::State;
+
+    use super::*;
+
+    fn states(states: &[State]) -> ToplevelEvent<u32> {
+        ToplevelEvent::State(
+            states
+                .iter()
+                .flat_map(|state| (*state as u32).to_ne_bytes())
+                .collect(),
+        )
+    }
+
+    /// Output 1 is on the left, output 2 on the right
+    fn output_rect(output: &u32) -> Option<Rect<i32>> {
+        match output {
+            1 => Some(Rect::new(0, 0, 1920, 1080)),
+            2 => Some(Rect::new(1920, 0, 2560, 1440)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_toplevel_events() {
+        let mut toplevel = Toplevel::new();
+        toplevel.apply(ToplevelEvent::Title("nvim".to_string()));
+        toplevel.apply(ToplevelEvent::AppId("foot".to_string()));
+        toplevel.apply(ToplevelEvent::OutputEnter(1));
+        toplevel.apply(states(&[State::Activated]));
+
+        // Titles and app ids are kept without a geometry
+        let window = toplevel.window(output_rect);
+        assert_eq!(window.title, "nvim");
+        assert_eq!(window.class, "foot");
+        assert_eq!(window.rect, None);
+        assert!(toplevel.activated);
+
+        // Moved to the other output and made fullscreen there
+        toplevel.apply(ToplevelEvent::OutputEnter(2));
+        toplevel.apply(ToplevelEvent::OutputLeave(1));
+        toplevel.apply(states(&[State::Fullscreen]));
+        assert!(!toplevel.activated);
+        assert_eq!(
+            toplevel.window(output_rect).rect,
+            Some(Rect::new(1920, 0, 2560, 1440))
+        );
+
+        // Minimized fullscreen toplevels aren't visible
+        toplevel.apply(states(&[State::Fullscreen, State::Minimized]));
+        assert_eq!(toplevel.window(output_rect).rect, None);
+
+        // Nor are ones that left every output
+        toplevel.apply(states(&[State::Fullscreen]));
+        toplevel.apply(ToplevelEvent::OutputLeave(2));
+        assert_eq!(toplevel.window(output_rect).rect, None);
+    }
+
+    #[test]
+    fn test_toplevel_output_enter_twice() {
+        let mut toplevel = Toplevel::new();
+        toplevel.apply(ToplevelEvent::OutputEnter(1));
+        toplevel.apply(ToplevelEvent::OutputEnter(1));
+        toplevel.apply(ToplevelEvent::OutputLeave(1));
+
+        assert!(toplevel.outputs.is_empty());
+    }
+
+    #[test]
+    fn test_toplevel_unknown_output() {
+        // Entering an output bound elsewhere on the connection as well as a known one
+        let mut toplevel = Toplevel::new();
+        toplevel.apply(ToplevelEvent::OutputEnter(3));
+        toplevel.apply(ToplevelEvent::OutputEnter(1));
+        toplevel.apply(states(&[State::Fullscreen]));
+
+        assert_eq!(
+            toplevel.window(output_rect).rect,
+            Some(Rect::new(0, 0, 1920, 1080))
+        );
+    }
+}
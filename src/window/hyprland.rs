@@ -40,9 +40,9 @@ impl CompositorBackend for HyprlandBackend {
             .map(WindowDescriptor::from)
     }
 
-    fn get_mouse_position(&self) -> (i32, i32) {
+    fn get_mouse_position(&self) -> Option<(i32, i32)> {
         let CursorPosition { x, y } = CursorPosition::get().unwrap();
-        (x as i32, y as i32)
+        Some((x as i32, y as i32))
     }
 }
 
@@ -63,12 +63,12 @@ impl From<Client> for WindowDescriptor {
             title: value.title,
             initial_class: value.initial_class,
             class: value.class,
-            rect: Rect {
+            rect: Some(Rect {
                 x: value.at.0 as i32,
                 y: value.at.1 as i32,
                 width: value.size.0 as i32,
                 height: value.size.1 as i32,
-            },
+            }),
         }
     }
 }
@@ -1,21 +1,26 @@
 use crate::{traits::Contains, types::Rect};
 
+pub mod foreign_toplevel;
 pub mod hyprland;
+pub mod niri;
 pub mod search;
+pub mod sway;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct WindowDescriptor {
     pub initial_title: String,
     pub title: String,
     pub initial_class: String,
     pub class: String,
-    pub rect: Rect<i32>,
+    /// `None` if the compositor doesn't tell where the window is, it can't be selected then
+    pub rect: Option<Rect<i32>>,
 }
 
 pub trait CompositorBackend {
     fn get_all_windows(&self) -> Vec<WindowDescriptor>;
     fn get_focused(&self) -> Option<WindowDescriptor>;
-    fn get_mouse_position(&self) -> (i32, i32);
+    /// Returns `None` if the compositor has no way to report the cursor position
+    fn get_mouse_position(&self) -> Option<(i32, i32)>;
 }
 
 pub trait InitializeBackend {
@@ -29,7 +34,8 @@ pub trait FindWindowExt {
 
 impl FindWindowExt for Vec<WindowDescriptor> {
     fn find_by_position(&self, position: &(i32, i32)) -> Option<&WindowDescriptor> {
-        self.iter().find(|window| window.rect.contains(position))
+        self.iter()
+            .find(|window| window.rect.is_some_and(|rect| rect.contains(position)))
     }
 
     fn find_by_search_param(&self, param: search::WindowSearchParam) -> Option<&WindowDescriptor> {
@@ -0,0 +1,365 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use log::error;
+use serde::Deserialize;
+
+use crate::types::Rect;
+
+use super::{CompositorBackend, CompositorNotAvailable, InitializeBackend, WindowDescriptor};
+
+pub struct NiriBackend {
+    socket_path: PathBuf,
+}
+
+impl NiriBackend {
+    fn new(socket_path: PathBuf) -> Self {
+        Self { socket_path }
+    }
+
+    /// Send a single request over a fresh connection and return the reply
+    fn request(&self, request: &str) -> io::Result<Response> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+        stream.write_all(format!("\"{}\"\n", request).as_bytes())?;
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+
+        let reply: Result<Response, String> = serde_json::from_str(&line)?;
+        reply.map_err(io::Error::other)
+    }
+
+    fn get_windows(&self) -> io::Result<Vec<(bool, WindowDescriptor)>> {
+        let Response::Windows(windows) = self.request("Windows")? else {
+            return Err(io::Error::other("Unexpected reply to the Windows request"));
+        };
+        let Response::Workspaces(workspaces) = self.request("Workspaces")? else {
+            return Err(io::Error::other(
+                "Unexpected reply to the Workspaces request",
+            ));
+        };
+        let Response::Outputs(outputs) = self.request("Outputs")? else {
+            return Err(io::Error::other("Unexpected reply to the Outputs request"));
+        };
+
+        Ok(visible_windows(windows, &workspaces, &outputs))
+    }
+}
+
+impl CompositorBackend for NiriBackend {
+    fn get_all_windows(&self) -> Vec<WindowDescriptor> {
+        match self.get_windows() {
+            Ok(windows) => windows.into_iter().map(|(_, window)| window).collect(),
+            Err(why) => {
+                error!("Failed to get the niri windows: {}", why);
+                Vec::new()
+            }
+        }
+    }
+
+    fn get_focused(&self) -> Option<WindowDescriptor> {
+        self.get_windows()
+            .inspect_err(|why| error!("Failed to get the niri windows: {}", why))
+            .ok()?
+            .into_iter()
+            .find_map(|(focused, window)| focused.then_some(window))
+    }
+
+    fn get_mouse_position(&self) -> Option<(i32, i32)> {
+        // The IPC does not expose the cursor position, so the window under the
+        // cursor is picked by the pointer entering the overlay, or is the focused
+        // window when capturing without one
+        None
+    }
+}
+
+impl InitializeBackend for NiriBackend {
+    fn try_new() -> Result<Box<dyn CompositorBackend>, CompositorNotAvailable> {
+        match std::env::var_os("NIRI_SOCKET") {
+            Some(socket_path) => Ok(Box::new(NiriBackend::new(socket_path.into()))),
+            None => Err(CompositorNotAvailable::NotRunning),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+enum Response {
+    Windows(Vec<Window>),
+    Workspaces(Vec<Workspace>),
+    Outputs(HashMap<String, Output>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Window {
+    title: Option<String>,
+    app_id: Option<String>,
+    workspace_id: Option<u64>,
+    is_focused: bool,
+    is_floating: bool,
+    layout: WindowLayout,
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowLayout {
+    window_size: (i32, i32),
+    /// Relative to the output, `None` if the window is not laid out on screen
+    tile_pos_in_workspace_view: Option<(f64, f64)>,
+    window_offset_in_tile: (f64, f64),
+}
+
+#[derive(Debug, Deserialize)]
+struct Workspace {
+    id: u64,
+    output: Option<String>,
+    is_active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Output {
+    logical: Option<LogicalOutput>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogicalOutput {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+/// Lay out the windows on the active workspaces in global logical coordinates,
+/// clipped to their output, topmost first. Each window is paired with whether it
+/// is focused.
+fn visible_windows(
+    windows: Vec<Window>,
+    workspaces: &[Workspace],
+    outputs: &HashMap<String, Output>,
+) -> Vec<(bool, WindowDescriptor)> {
+    let mut visible: Vec<_> = windows
+        .into_iter()
+        .filter_map(|window| {
+            let workspace = workspaces
+                .iter()
+                .find(|workspace| Some(workspace.id) == window.workspace_id)?;
+
+            if !workspace.is_active {
+                return None;
+            }
+
+            let output = outputs.get(workspace.output.as_ref()?)?.logical.as_ref()?;
+            let output_rect = Rect::new(output.x, output.y, output.width, output.height);
+
+            let (tile_x, tile_y) = window.layout.tile_pos_in_workspace_view?;
+            let (offset_x, offset_y) = window.layout.window_offset_in_tile;
+            let (width, height) = window.layout.window_size;
+
+            // Windows partly scrolled off screen only keep the part on their output
+            let rect = Rect::new(
+                output.x + (tile_x + offset_x).round() as i32,
+                output.y + (tile_y + offset_y).round() as i32,
+                width,
+                height,
+            )
+            .intersection(&output_rect)?;
+
+            let title = window.title.unwrap_or_default();
+            let class = window.app_id.unwrap_or_default();
+
+            Some((
+                (window.is_focused, window.is_floating),
+                WindowDescriptor {
+                    initial_title: title.clone(),
+                    title,
+                    initial_class: class.clone(),
+                    class,
+                    rect: Some(rect),
+                },
+            ))
+        })
+        .collect();
+
+    // Floating windows are drawn above the tiled ones, and the focused one above the rest
+    visible.sort_by_key(|((focused, floating), _)| (!floating, !focused));
+
+    visible
+        .into_iter()
+        .map(|((focused, _), window)| (focused, window))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixListener, thread};
+
+    use serde_json::Value;
+
+    use super::*;
+
+    const WINDOWS: &str = r#"[
+        {
+            "id": 1, "title": "nvim", "app_id": "foot", "pid": 100, "workspace_id": 1,
+            "is_focused": true, "is_floating": false, "is_urgent": false,
+            "layout": {
+                "pos_in_scrolling_layout": [1, 1], "tile_size": [960.0, 1080.0],
+                "window_size": [960, 1080], "tile_pos_in_workspace_view": [0.0, 0.0],
+                "window_offset_in_tile": [0.0, 0.0]
+            }
+        },
+        {
+            "id": 2, "title": "Firefox", "app_id": "firefox", "pid": 101, "workspace_id": 1,
+            "is_focused": false, "is_floating": false, "is_urgent": false,
+            "layout": {
+                "pos_in_scrolling_layout": [2, 1], "tile_size": [1280.0, 1080.0],
+                "window_size": [1280, 1080], "tile_pos_in_workspace_view": [960.0, 0.0],
+                "window_offset_in_tile": [0.0, 0.0]
+            }
+        },
+        {
+            "id": 3, "title": "Files", "app_id": "nautilus", "pid": 102, "workspace_id": 1,
+            "is_focused": false, "is_floating": false, "is_urgent": false,
+            "layout": {
+                "pos_in_scrolling_layout": [3, 1], "tile_size": [960.0, 1080.0],
+                "window_size": [960, 1080], "tile_pos_in_workspace_view": [2240.0, 0.0],
+                "window_offset_in_tile": [0.0, 0.0]
+            }
+        },
+        {
+            "id": 4, "title": "Calculator", "app_id": "gnome-calculator", "pid": 103,
+            "workspace_id": 1, "is_focused": false, "is_floating": true, "is_urgent": false,
+            "layout": {
+                "pos_in_scrolling_layout": null, "tile_size": [400.0, 500.0],
+                "window_size": [400, 500], "tile_pos_in_workspace_view": [700.0, 300.0],
+                "window_offset_in_tile": [0.0, 0.0]
+            }
+        },
+        {
+            "id": 5, "title": "Steam", "app_id": "steam", "pid": 104, "workspace_id": 2,
+            "is_focused": false, "is_floating": false, "is_urgent": false,
+            "layout": {
+                "pos_in_scrolling_layout": [1, 1], "tile_size": [2560.0, 1440.0],
+                "window_size": [2560, 1440], "tile_pos_in_workspace_view": [0.0, 0.0],
+                "window_offset_in_tile": [0.0, 0.0]
+            }
+        }
+    ]"#;
+
+    const WORKSPACES: &str = r#"[
+        { "id": 1, "idx": 1, "name": null, "output": "eDP-1", "is_urgent": false,
+          "is_active": true, "is_focused": true, "active_window_id": 1 },
+        { "id": 2, "idx": 1, "name": null, "output": "HDMI-A-1", "is_urgent": false,
+          "is_active": false, "is_focused": false, "active_window_id": 5 }
+    ]"#;
+
+    const OUTPUTS: &str = r#"{
+        "eDP-1": {
+            "name": "eDP-1", "make": "BOE", "model": "0x0BCA", "serial": null,
+            "logical": { "x": 0, "y": 0, "width": 1920, "height": 1080,
+                         "scale": 1.0, "transform": "Normal" }
+        },
+        "HDMI-A-1": {
+            "name": "HDMI-A-1", "make": "Dell", "model": "U2719D", "serial": null,
+            "logical": { "x": 1920, "y": 0, "width": 2560, "height": 1440,
+                         "scale": 1.0, "transform": "Normal" }
+        }
+    }"#;
+
+    #[test]
+    fn test_visible_windows() {
+        let windows = visible_windows(
+            serde_json::from_str(WINDOWS).unwrap(),
+            &serde_json::from_str::<Vec<Workspace>>(WORKSPACES).unwrap(),
+            &serde_json::from_str(OUTPUTS).unwrap(),
+        );
+
+        let titles: Vec<_> = windows
+            .iter()
+            .map(|(_, window)| window.title.as_str())
+            .collect();
+
+        // "Files" is scrolled off screen and "Steam" is on an inactive workspace
+        assert_eq!(titles, ["Calculator", "nvim", "Firefox"]);
+
+        assert!(windows[1].0);
+        assert_eq!(windows[1].1.rect, Some(Rect::new(0, 0, 960, 1080)));
+        // Partly scrolled off screen, clipped to the output
+        assert_eq!(windows[2].1.rect, Some(Rect::new(960, 0, 960, 1080)));
+    }
+
+    /// A successful reply to the request, on a single line like niri sends it
+    fn reply(request: &str, json: &str) -> String {
+        let value: Value = serde_json::from_str(json).unwrap();
+        serde_json::json!({ "Ok": { request: value } }).to_string()
+    }
+
+    /// Answer each request with the reply for it, one per connection, like the
+    /// niri IPC socket would
+    fn fake_ipc_socket(name: &str, replies: Vec<(&'static str, String)>) -> PathBuf {
+        let socket_path = std::env::temp_dir().join(format!(
+            "watershot-niri-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        thread::spawn(move || {
+            for (request, reply) in replies {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                assert_eq!(line, format!("\"{}\"\n", request));
+
+                writeln!(reader.get_mut(), "{}", reply).unwrap();
+            }
+        });
+
+        socket_path
+    }
+
+    fn windows_replies() -> Vec<(&'static str, String)> {
+        vec![
+            ("Windows", reply("Windows", WINDOWS)),
+            ("Workspaces", reply("Workspaces", WORKSPACES)),
+            ("Outputs", reply("Outputs", OUTPUTS)),
+        ]
+    }
+
+    #[test]
+    fn test_get_all_windows() {
+        let backend = NiriBackend::new(fake_ipc_socket("all-windows", windows_replies()));
+
+        let windows = backend.get_all_windows();
+        let titles: Vec<_> = windows.iter().map(|window| window.title.as_str()).collect();
+
+        assert_eq!(titles, ["Calculator", "nvim", "Firefox"]);
+        assert_eq!(windows[0].class, "gnome-calculator");
+        assert_eq!(windows[0].rect, Some(Rect::new(700, 300, 400, 500)));
+    }
+
+    #[test]
+    fn test_get_focused() {
+        let backend = NiriBackend::new(fake_ipc_socket("focused", windows_replies()));
+
+        let focused = backend.get_focused().unwrap();
+
+        assert_eq!(focused.title, "nvim");
+        assert_eq!(focused.class, "foot");
+        assert_eq!(focused.rect, Some(Rect::new(0, 0, 960, 1080)));
+    }
+
+    #[test]
+    fn test_error_reply() {
+        let backend = NiriBackend::new(fake_ipc_socket(
+            "error",
+            vec![("Windows", r#"{"Err":"Not available"}"#.to_string())],
+        ));
+
+        assert!(backend.get_all_windows().is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use log::error;
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::types::Rect;
+
+use super::{CompositorBackend, CompositorNotAvailable, InitializeBackend, WindowDescriptor};
+
+const IPC_MAGIC: &[u8; 6] = b"i3-ipc";
+const IPC_GET_TREE: u32 = 4;
+
+pub struct SwayBackend {
+    socket_path: PathBuf,
+}
+
+impl SwayBackend {
+    fn new(socket_path: PathBuf) -> Self {
+        Self { socket_path }
+    }
+
+    /// Send a single message over a fresh connection and deserialize the reply
+    fn request<T: DeserializeOwned>(&self, message_type: u32, payload: &str) -> io::Result<T> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+
+        let mut message = Vec::with_capacity(IPC_MAGIC.len() + 8 + payload.len());
+        message.extend_from_slice(IPC_MAGIC);
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&message_type.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        stream.write_all(&message)?;
+
+        let mut header = [0; 14];
+        stream.read_exact(&mut header)?;
+
+        if &header[..6] != IPC_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid magic in IPC reply",
+            ));
+        }
+
+        let length = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+        let mut body = vec![0; length as usize];
+        stream.read_exact(&mut body)?;
+
+        serde_json::from_slice(&body).map_err(io::Error::from)
+    }
+
+    fn get_tree(&self) -> Option<Node> {
+        self.request(IPC_GET_TREE, "")
+            .inspect_err(|why| error!("Failed to get the sway tree: {}", why))
+            .ok()
+    }
+}
+
+impl CompositorBackend for SwayBackend {
+    fn get_all_windows(&self) -> Vec<WindowDescriptor> {
+        let Some(tree) = self.get_tree() else {
+            return Vec::new();
+        };
+
+        let mut windows = Vec::new();
+
+        for output in tree.nodes.iter().filter(|node| node.is_visible_output()) {
+            let Some(workspace) = output.nodes.iter().find(|workspace| {
+                workspace.node_type == NodeType::Workspace
+                    && workspace.name == output.current_workspace
+            }) else {
+                continue;
+            };
+
+            workspace.collect_visible_windows(&mut windows);
+        }
+
+        windows
+    }
+
+    fn get_focused(&self) -> Option<WindowDescriptor> {
+        self.get_tree()?
+            .find_focused_window()
+            .map(WindowDescriptor::from)
+    }
+
+    fn get_mouse_position(&self) -> Option<(i32, i32)> {
+        // The IPC does not expose the cursor position
+        None
+    }
+}
+
+impl InitializeBackend for SwayBackend {
+    fn try_new() -> Result<Box<dyn CompositorBackend>, CompositorNotAvailable> {
+        match std::env::var_os("SWAYSOCK") {
+            Some(socket_path) => Ok(Box::new(SwayBackend::new(socket_path.into()))),
+            None => Err(CompositorNotAvailable::NotRunning),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum NodeType {
+    Root,
+    Output,
+    Workspace,
+    Con,
+    FloatingCon,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct SwayRect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowProperties {
+    class: Option<String>,
+}
+
+/// A node in the `GET_TREE` reply, only containing the fields we care about
+#[derive(Debug, Deserialize)]
+struct Node {
+    #[serde(rename = "type")]
+    node_type: NodeType,
+    name: Option<String>,
+    rect: SwayRect,
+    #[serde(default)]
+    focused: bool,
+    visible: Option<bool>,
+    /// Set for native wayland windows
+    app_id: Option<String>,
+    /// Set for xwayland windows
+    window_properties: Option<WindowProperties>,
+    /// Set for outputs
+    current_workspace: Option<String>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+impl Node {
+    fn is_window(&self) -> bool {
+        matches!(self.node_type, NodeType::Con | NodeType::FloatingCon)
+            && (self.app_id.is_some() || self.window_properties.is_some())
+    }
+
+    fn is_visible_output(&self) -> bool {
+        // The scratchpad lives on the internal `__i3` output
+        self.node_type == NodeType::Output
+            && self.current_workspace.is_some()
+            && self.name.as_deref() != Some("__i3")
+    }
+
+    /// Collect the visible windows under this node, topmost first
+    fn collect_visible_windows(&self, windows: &mut Vec<WindowDescriptor>) {
+        // Floating windows are stacked above the tiled ones, with the last one on top
+        for node in self.floating_nodes.iter().rev().chain(&self.nodes) {
+            if node.is_window() {
+                if node.visible.unwrap_or(false) {
+                    windows.push(WindowDescriptor::from(node));
+                }
+            } else {
+                node.collect_visible_windows(windows);
+            }
+        }
+    }
+
+    fn find_focused_window(&self) -> Option<&Node> {
+        if self.focused && self.is_window() {
+            return Some(self);
+        }
+
+        self.nodes
+            .iter()
+            .chain(&self.floating_nodes)
+            .find_map(Node::find_focused_window)
+    }
+}
+
+impl From<&Node> for WindowDescriptor {
+    fn from(value: &Node) -> Self {
+        let title = value.name.clone().unwrap_or_default();
+        let class = value
+            .app_id
+            .clone()
+            .or_else(|| {
+                value
+                    .window_properties
+                    .as_ref()
+                    .and_then(|properties| properties.class.clone())
+            })
+            .unwrap_or_default();
+
+        // Sway does not keep track of the initial values
+        Self {
+            initial_title: title.clone(),
+            title,
+            initial_class: class.clone(),
+            class,
+            rect: Some(Rect {
+                x: value.rect.x,
+                y: value.rect.y,
+                width: value.rect.width,
+                height: value.rect.height,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixListener, thread};
+
+    use super::*;
+
+    const GET_TREE_REPLY: &str = include_str!("../../res/fixtures/sway_get_tree.json");
+
+    /// Serve `replies` in order, one per connection, like the sway IPC socket would
+    fn fake_ipc_socket(name: &str, replies: Vec<&'static str>) -> PathBuf {
+        let socket_path =
+            std::env::temp_dir().join(format!("watershot-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        thread::spawn(move || {
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+
+                let mut header = [0; 14];
+                stream.read_exact(&mut header).unwrap();
+                assert_eq!(&header[..6], IPC_MAGIC);
+                let message_type = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+
+                let mut response = Vec::new();
+                response.extend_from_slice(IPC_MAGIC);
+                response.extend_from_slice(&(reply.len() as u32).to_ne_bytes());
+                response.extend_from_slice(&message_type.to_ne_bytes());
+                response.extend_from_slice(reply.as_bytes());
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        socket_path
+    }
+
+    #[test]
+    fn test_get_all_windows() {
+        let backend = SwayBackend::new(fake_ipc_socket("all-windows", vec![GET_TREE_REPLY]));
+
+        let windows = backend.get_all_windows();
+        let titles: Vec<_> = windows.iter().map(|window| window.title.as_str()).collect();
+
+        // The hidden tab, the scratchpad and the inactive workspace are skipped,
+        // floating windows come before the tiled ones
+        assert_eq!(titles, ["Calculator", "nvim", "Firefox", "Steam"]);
+
+        assert_eq!(windows[0].class, "gnome-calculator");
+        assert_eq!(windows[3].class, "steam");
+        assert_eq!(windows[3].rect, Some(Rect::new(1920, 0, 2560, 1440)));
+    }
+
+    #[test]
+    fn test_get_focused() {
+        let backend = SwayBackend::new(fake_ipc_socket("focused", vec![GET_TREE_REPLY]));
+
+        let focused = backend.get_focused().unwrap();
+
+        assert_eq!(focused.title, "nvim");
+        assert_eq!(focused.class, "foot");
+        assert_eq!(focused.rect, Some(Rect::new(0, 0, 960, 1080)));
+    }
+}